@@ -0,0 +1,110 @@
+// Base64 (RFC 4648) encoding and decoding for `!!binary` scalars.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn value(c: char) -> Option<u32> {
+    match c {
+        'A'...'Z' => Some(c as u32 - 'A' as u32),
+        'a'...'z' => Some(c as u32 - 'a' as u32 + 26),
+        '0'...'9' => Some(c as u32 - '0' as u32 + 52),
+        '+' => Some(62),
+        '/' => Some(63),
+        _ => None,
+    }
+}
+
+pub fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode base64 text, ignoring whitespace such as the line breaks of a block
+/// scalar. Returns a description of the problem if the text is malformed.
+pub fn decode(text: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut group = 0u32;
+    let mut len = 0;
+    let mut padding = 0;
+    for c in text.chars() {
+        if c == ' ' || c == '\t' || c == '\n' || c == '\r' {
+            continue;
+        }
+        if c == '=' {
+            // only the last one or two characters of a group may be padding
+            if len % 4 < 2 || padding == 2 {
+                return Err("unexpected padding".to_owned());
+            }
+            padding += 1;
+            len += 1;
+            continue;
+        }
+        if padding > 0 {
+            return Err(format!("unexpected {:?} after padding", c));
+        }
+        match value(c) {
+            Some(v) => group = group << 6 | v,
+            None => return Err(format!("invalid character {:?}", c)),
+        }
+        len += 1;
+        if len % 4 == 0 {
+            out.push((group >> 16) as u8);
+            out.push((group >> 8) as u8);
+            out.push(group as u8);
+            group = 0;
+        }
+    }
+    if len % 4 != 0 {
+        return Err("truncated data".to_owned());
+    }
+    if padding > 0 {
+        // the partial group was never flushed: it holds 4 - padding characters
+        let bits = 6 * (4 - padding);
+        let bytes = 3 - padding;
+        group <<= 24 - bits;
+        for i in 0..bytes {
+            out.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for &(data, text) in &[
+            (&b""[..], ""),
+            (&b"f"[..], "Zg=="),
+            (&b"fo"[..], "Zm8="),
+            (&b"foo"[..], "Zm9v"),
+            (&b"foob"[..], "Zm9vYg=="),
+            (&b"fooba"[..], "Zm9vYmE="),
+            (&b"foobar"[..], "Zm9vYmFy"),
+            (&[0xff, 0xfe, 0x00][..], "//4A"),
+        ] {
+            assert_eq!(encode(data), text);
+            assert_eq!(decode(text).unwrap(), data);
+        }
+        assert_eq!(decode(" Zm9v\n YmFy\n").unwrap(), b"foobar");
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(decode("Zm9v!"), Err("invalid character '!'".to_owned()));
+        assert_eq!(decode("Zm9"), Err("truncated data".to_owned()));
+        assert_eq!(decode("Z==="), Err("unexpected padding".to_owned()));
+        assert_eq!(decode("Zg==Zg=="), Err("unexpected 'Z' after padding".to_owned()));
+    }
+}
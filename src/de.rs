@@ -0,0 +1,932 @@
+//! Deserialization of Rust values from YAML text with serde, enabled by the `serde`
+//! feature.
+//!
+//! The deserializer reads `Parser` events directly, without building a `Yaml`
+//! tree. Plain scalars are resolved like `Yaml::from_str`, while quoted and block
+//! scalars are always strings; a `String` field also accepts any scalar, so
+//! `version: 1.10` reads as `"1.10"`. Aliases are replayed from their anchored
+//! nodes, and only the first document of a stream may be present.
+//!
+//! Enums are read from a plain scalar for unit variants, or from a single-entry
+//! mapping `{Variant: value}` or a local tag `!Variant value` for variants with
+//! data. Scalars copied verbatim from the input, such as plain and quoted scalars
+//! without escapes or line folding, can be borrowed as `&str`.
+//!
+//! Untrusted input is bounded by default: nesting may be at most
+//! `DEFAULT_MAX_DEPTH` deep, and aliases may replay at most
+//! `DEFAULT_MAX_ALIAS_RATIO` times as many events as the document holds. Use
+//! `Deserializer::with_max_depth`, `Deserializer::max_aliases` and
+//! `Deserializer::max_alias_ratio` to change the limits.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! # extern crate yaml_rust;
+//! # fn main() {
+//! #[derive(Deserialize, Debug, PartialEq)]
+//! enum Shape {
+//!     Circle { r: f64 },
+//!     Point,
+//! }
+//!
+//! #[derive(Deserialize, Debug, PartialEq)]
+//! struct Drawing<'a> {
+//!     title: &'a str,
+//!     shapes: Vec<Shape>,
+//! }
+//!
+//! let drawing: Drawing = yaml_rust::de::from_str("
+//! title: sketch
+//! shapes: [!Circle {r: 1.5}, Point]
+//! ").unwrap();
+//! assert_eq!(drawing.title, "sketch");
+//! assert_eq!(drawing.shapes, vec![Shape::Circle { r: 1.5 }, Shape::Point]);
+//!
+//! let err = yaml_rust::de::from_str::<Drawing>("title: x\nshapes: [Square]").unwrap_err();
+//! assert_eq!(err.to_string(),
+//!            "unknown variant `Square`, expected `Circle` or `Point` at line 2 column 10");
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Unexpected, Visitor};
+use base64;
+use parser::{Event, MarkedEventReceiver, Parser};
+use scanner::{Marker, ScanError, TScalarStyle, TokenType};
+use timestamp::Timestamp;
+use yaml::Yaml;
+
+/// An error while deserializing, with the position of the offending node when
+/// it is known.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub struct Error {
+    mark: Option<Marker>,
+    info: String,
+}
+
+impl Error {
+    fn new(mark: Marker, info: &str) -> Error {
+        Error {
+            mark: Some(mark),
+            info: info.to_owned(),
+        }
+    }
+
+    // Attach `mark` to an error raised by a visitor, unless it already has one.
+    fn at(mut self, mark: Marker) -> Error {
+        if self.mark.is_none() {
+            self.mark = Some(mark);
+        }
+        self
+    }
+
+    pub fn marker(&self) -> Option<&Marker> {
+        self.mark.as_ref()
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        self.info.as_ref()
+    }
+}
+
+impl fmt::Display for Error {
+    // col starts from 0
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.mark {
+            Some(mark) => write!(formatter, "{} at line {} column {}", self.info,
+                                 mark.line(), mark.col() + 1),
+            None => formatter.write_str(&self.info),
+        }
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error {
+            mark: None,
+            info: msg.to_string(),
+        }
+    }
+}
+
+impl From<ScanError> for Error {
+    fn from(err: ScanError) -> Error {
+        Error::new(*err.marker(), &error::Error::description(&err).to_owned())
+    }
+}
+
+// A parser event, with the source text of a scalar when it equals the value.
+struct Item<'de> {
+    event: Event,
+    mark: Marker,
+    borrowed: Option<&'de str>,
+}
+
+// Collects the events of the documents in the input.
+struct EventLoader<'de> {
+    input: &'de str,
+    // char and byte offsets of the last marker looked up in `input`
+    offset: (usize, usize),
+    items: Vec<Item<'de>>,
+    documents: Vec<Marker>,
+}
+
+impl<'de> EventLoader<'de> {
+    fn byte_offset(&mut self, index: usize) -> Option<usize> {
+        if index < self.offset.0 {
+            self.offset = (0, 0);
+        }
+        let (mut chars, mut bytes) = self.offset;
+        let mut rest = self.input[bytes..].chars();
+        while chars < index {
+            match rest.next() {
+                Some(c) => {
+                    chars += 1;
+                    bytes += c.len_utf8();
+                },
+                None => return None,
+            }
+        }
+        self.offset = (chars, bytes);
+        Some(bytes)
+    }
+
+    // The source text of a scalar, if the value is a verbatim copy of it.
+    fn source(&mut self, value: &str, style: TScalarStyle, mark: Marker, end: Marker)
+        -> Option<&'de str> {
+        let start = match self.byte_offset(mark.index()) {
+            Some(start) => start,
+            None => return None,
+        };
+        let end = match self.byte_offset(end.index()) {
+            Some(end) if end >= start => end,
+            _ => return None,
+        };
+        let text = &self.input[start..end];
+        let text = match style {
+            TScalarStyle::Plain => text,
+            // the quotes are single bytes
+            TScalarStyle::SingleQuoted | TScalarStyle::DoubleQuoted if text.len() >= 2 => {
+                &text[1..text.len() - 1]
+            },
+            _ => return None,
+        };
+        if text == value { Some(text) } else { None }
+    }
+}
+
+impl<'de> MarkedEventReceiver for EventLoader<'de> {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        self.on_spanned_event(ev, mark, mark)
+    }
+
+    fn on_spanned_event(&mut self, ev: Event, mark: Marker, end: Marker) {
+        let borrowed = match ev {
+            Event::DocumentStart => {
+                self.documents.push(mark);
+                return;
+            },
+            Event::Nothing | Event::StreamStart | Event::StreamEnd | Event::DocumentEnd => return,
+            // only the first document is deserialized
+            _ if self.documents.len() > 1 => return,
+            Event::Scalar(ref v, style, _, _) => self.source(v, style, mark, end),
+            _ => None,
+        };
+        self.items.push(Item {
+            event: ev,
+            mark: mark,
+            borrowed: borrowed,
+        });
+    }
+}
+
+fn local_tag(tag: &Option<TokenType>) -> Option<&str> {
+    match *tag {
+        Some(TokenType::Tag(ref handle, ref suffix)) if handle == "!" => Some(suffix),
+        _ => None,
+    }
+}
+
+fn core_tag(tag: &Option<TokenType>) -> Option<&str> {
+    match *tag {
+        Some(TokenType::Tag(ref handle, ref suffix)) if handle == "!!" => Some(suffix),
+        _ => None,
+    }
+}
+
+fn is_null(v: &str, style: TScalarStyle, tag: &Option<TokenType>) -> bool {
+    if style != TScalarStyle::Plain {
+        return false;
+    }
+    match core_tag(tag) {
+        Some("null") | None => v == "~" || v == "null" || v.is_empty(),
+        _ => false,
+    }
+}
+
+enum Kind {
+    Scalar,
+    Sequence,
+    Mapping,
+}
+
+/// How deeply sequences and mappings may be nested by default, counting those
+/// replayed from aliases.
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// How many times as many events as the document holds aliases may replay by
+/// default.
+pub const DEFAULT_MAX_ALIAS_RATIO: f64 = 100.0;
+
+/// A deserializer reading the single document of a YAML stream.
+pub struct Deserializer<'de> {
+    items: Vec<Item<'de>>,
+    pos: usize,
+    // item range of each anchored node
+    anchors: HashMap<usize, (usize, usize)>,
+    // end and resume position of each alias being replayed
+    replays: Vec<(usize, usize)>,
+    // position of a node whose local tag was read as an enum variant
+    variant_tag: Option<usize>,
+    // number of aliases and of events they replayed so far
+    aliases: usize,
+    replayed: usize,
+    max_aliases: Option<usize>,
+    max_alias_ratio: f64,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<'de> Deserializer<'de> {
+    /// Parse `input`, failing on syntax errors or if it holds more than one
+    /// document. An empty input deserializes as null.
+    pub fn new(input: &'de str) -> Result<Deserializer<'de>, Error> {
+        Deserializer::with_max_depth(input, DEFAULT_MAX_DEPTH)
+    }
+
+    /// Like `new`, but allowing sequences and mappings to be nested `limit` deep
+    /// instead of `DEFAULT_MAX_DEPTH`. See `Parser::max_depth`.
+    pub fn with_max_depth(input: &'de str, limit: usize) -> Result<Deserializer<'de>, Error> {
+        let mut loader = EventLoader {
+            input: input,
+            offset: (0, 0),
+            items: Vec::new(),
+            documents: Vec::new(),
+        };
+        let mut parser = Parser::new(input.chars());
+        parser.max_depth(limit);
+        try!(parser.load(&mut loader, true));
+        if loader.documents.len() > 1 {
+            return Err(Error::new(loader.documents[1], "expected a single document"));
+        }
+        if loader.items.is_empty() {
+            loader.items.push(Item {
+                event: Event::Scalar("~".to_owned(), TScalarStyle::Plain, 0, None),
+                mark: Marker::new(0, 1, 0),
+                borrowed: None,
+            });
+        }
+
+        let mut anchors = HashMap::new();
+        let mut starts = Vec::new();
+        for (i, item) in loader.items.iter().enumerate() {
+            match item.event {
+                Event::Scalar(_, _, aid, _) if aid > 0 => {
+                    anchors.insert(aid, (i, i + 1));
+                },
+                Event::SequenceStart(aid, _) | Event::MappingStart(aid, _) => starts.push((aid, i)),
+                Event::SequenceEnd | Event::MappingEnd => {
+                    let (aid, start) = starts.pop().unwrap();
+                    if aid > 0 {
+                        anchors.insert(aid, (start, i + 1));
+                    }
+                },
+                _ => {},
+            }
+        }
+        Ok(Deserializer {
+            items: loader.items,
+            pos: 0,
+            anchors: anchors,
+            replays: Vec::new(),
+            variant_tag: None,
+            aliases: 0,
+            replayed: 0,
+            max_aliases: None,
+            max_alias_ratio: DEFAULT_MAX_ALIAS_RATIO,
+            depth: 0,
+            max_depth: limit,
+        })
+    }
+
+    /// Limit the number of aliases replayed while deserializing, counting
+    /// aliases met again inside a replayed node. Unlimited by default.
+    pub fn max_aliases(&mut self, limit: usize) {
+        self.max_aliases = Some(limit);
+    }
+
+    /// Limit the number of events replayed from aliases to `ratio` times the
+    /// number of events in the document, `DEFAULT_MAX_ALIAS_RATIO` by default.
+    ///
+    /// Together with `max_aliases` this protects against "billion laughs"
+    /// documents, whose few nested aliases expand to an enormous tree. The limits
+    /// are checked before an alias is replayed, and deserializing fails with an
+    /// error pointing at the first alias that exceeds them.
+    pub fn max_alias_ratio(&mut self, ratio: f64) {
+        self.max_alias_ratio = ratio;
+    }
+
+    // Account for an alias at `mark` replaying `size` events.
+    fn count_alias(&mut self, size: usize, mark: Marker) -> Result<(), Error> {
+        self.aliases += 1;
+        if let Some(limit) = self.max_aliases {
+            if self.aliases > limit {
+                return Err(Error::new(mark, &format!("document exceeds the limit of {} aliases", limit)));
+            }
+        }
+        self.replayed = self.replayed.saturating_add(size);
+        if self.replayed as f64 > self.max_alias_ratio * self.items.len() as f64 {
+            return Err(Error::new(mark, &format!("aliases expand the document more than {} times",
+                                                 self.max_alias_ratio)));
+        }
+        Ok(())
+    }
+
+    // Enter the sequence or mapping at `pos`, which may be nested more deeply
+    // than the parser saw when it is replayed from an alias.
+    fn enter(&mut self, pos: usize) -> Result<(), Error> {
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            return Err(Error::new(self.items[pos].mark, "exceeded the maximum nesting depth"));
+        }
+        Ok(())
+    }
+
+    fn end_replays(&mut self) {
+        while let Some(&(end, resume)) = self.replays.last() {
+            if self.pos != end {
+                break;
+            }
+            self.pos = resume;
+            self.replays.pop();
+        }
+    }
+
+    // The position of the next event, after jumping to the anchored node if it
+    // is an alias.
+    fn peek(&mut self) -> Result<usize, Error> {
+        loop {
+            self.end_replays();
+            let item = match self.items.get(self.pos) {
+                Some(item) => item,
+                None => {
+                    let mark = self.items.last().unwrap().mark;
+                    return Err(Error::new(mark, "unexpected end of document"));
+                },
+            };
+            let id = match item.event {
+                Event::Alias(id) => id,
+                _ => return Ok(self.pos),
+            };
+            match self.anchors.get(&id) {
+                Some(&(start, end)) if start <= self.pos && self.pos < end => {
+                    return Err(Error::new(item.mark, "recursive alias"));
+                },
+                Some(&(start, end)) => {
+                    let mark = item.mark;
+                    try!(self.count_alias(end - start, mark));
+                    self.replays.push((end, self.pos + 1));
+                    self.pos = start;
+                },
+                None => return Err(Error::new(item.mark, "unknown anchor")),
+            }
+        }
+    }
+
+    fn next(&mut self) -> Result<usize, Error> {
+        let pos = try!(self.peek());
+        if self.variant_tag == Some(pos) {
+            self.variant_tag = None;
+        }
+        self.pos = pos + 1;
+        Ok(pos)
+    }
+
+    fn kind(&self, pos: usize) -> Kind {
+        match self.items[pos].event {
+            Event::SequenceStart(..) => Kind::Sequence,
+            Event::MappingStart(..) => Kind::Mapping,
+            _ => Kind::Scalar,
+        }
+    }
+
+    fn is_null(&self, pos: usize) -> bool {
+        match self.items[pos].event {
+            Event::Scalar(ref v, style, _, ref tag) => is_null(v, style, tag),
+            _ => false,
+        }
+    }
+
+    // Consume the end of the sequence or mapping being read.
+    fn end(&mut self, pos: usize) -> Result<(), Error> {
+        let end = try!(self.peek());
+        match (&self.items[pos].event, &self.items[end].event) {
+            (&Event::SequenceStart(..), &Event::SequenceEnd)
+                | (&Event::MappingStart(..), &Event::MappingEnd) => {
+                self.pos = end + 1;
+                self.depth -= 1;
+                Ok(())
+            },
+            (&Event::SequenceStart(..), _) => {
+                Err(Error::new(self.items[end].mark, "expected the end of the sequence"))
+            },
+            _ => Err(Error::new(self.items[end].mark, "expected the end of the mapping")),
+        }
+    }
+
+    // Skip the next node without replaying aliases.
+    fn skip(&mut self) {
+        let mut depth = 0;
+        loop {
+            self.end_replays();
+            match self.items[self.pos].event {
+                Event::SequenceStart(..) | Event::MappingStart(..) => depth += 1,
+                Event::SequenceEnd | Event::MappingEnd => depth -= 1,
+                _ => {},
+            }
+            self.pos += 1;
+            if depth == 0 {
+                return;
+            }
+        }
+    }
+
+    fn visit_text<V: Visitor<'de>>(&self, pos: usize, visitor: V) -> Result<V::Value, Error> {
+        let item = &self.items[pos];
+        match (item.borrowed, &item.event) {
+            (Some(text), _) => visitor.visit_borrowed_str(text),
+            (None, &Event::Scalar(ref v, ..)) => visitor.visit_str(v),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_scalar<V: Visitor<'de>>(&self, pos: usize, visitor: V) -> Result<V::Value, Error> {
+        let item = &self.items[pos];
+        let (v, style, tag) = match item.event {
+            Event::Scalar(ref v, style, _, ref tag) => (v, style, tag),
+            _ => unreachable!(),
+        };
+        let invalid = |suffix| Error::new(item.mark, &format!("invalid value {:?} for !!{}", v, suffix));
+        let node = match core_tag(tag) {
+            Some("binary") => {
+                return match base64::decode(v) {
+                    Ok(data) => visitor.visit_byte_buf(data),
+                    Err(e) => Err(Error::new(item.mark, &format!("invalid base64 in !!binary scalar: {}", e))),
+                };
+            },
+            Some("str") => return self.visit_text(pos, visitor),
+            _ if style != TScalarStyle::Plain => return self.visit_text(pos, visitor),
+            _ if is_null(v, style, tag) => Yaml::Null,
+            Some("null") => return Err(invalid("null")),
+            Some("bool") => {
+                match v.parse::<bool>() {
+                    Ok(b) => Yaml::Boolean(b),
+                    Err(_) => return Err(invalid("bool")),
+                }
+            },
+            Some("int") => {
+                match Yaml::from_str(v) {
+                    n @ Yaml::Integer(_) | n @ Yaml::BigInteger(_) => n,
+                    _ => return Err(invalid("int")),
+                }
+            },
+            Some("float") => {
+                match Yaml::Real(v.clone()).as_f64() {
+                    Some(_) => Yaml::Real(v.clone()),
+                    None => return Err(invalid("float")),
+                }
+            },
+            Some("timestamp") if Timestamp::parse(v).is_none() => return Err(invalid("timestamp")),
+            _ => Yaml::from_str(v),
+        };
+        match node {
+            Yaml::Integer(n) => visitor.visit_i64(n),
+            Yaml::BigInteger(ref n) => {
+                if let Ok(n) = n.parse::<u64>() {
+                    visitor.visit_u64(n)
+                } else if let Ok(n) = n.parse::<i128>() {
+                    visitor.visit_i128(n)
+                } else if let Ok(n) = n.parse::<u128>() {
+                    visitor.visit_u128(n)
+                } else {
+                    Err(Error::new(item.mark, &format!("integer {} is out of range", n)))
+                }
+            },
+            Yaml::Real(_) => visitor.visit_f64(node.as_f64().unwrap()),
+            Yaml::Boolean(b) => visitor.visit_bool(b),
+            Yaml::Null => visitor.visit_unit(),
+            _ => self.visit_text(pos, visitor),
+        }
+    }
+}
+
+/// Deserialize a `T` from the single document in `input`.
+pub fn from_str<'de, T: de::Deserialize<'de>>(input: &'de str) -> Result<T, Error> {
+    let mut deserializer = try!(Deserializer::new(input));
+    T::deserialize(&mut deserializer)
+}
+
+impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let pos = try!(self.next());
+        let mark = self.items[pos].mark;
+        match self.kind(pos) {
+            Kind::Scalar => self.visit_scalar(pos, visitor).map_err(|e| e.at(mark)),
+            Kind::Sequence => {
+                try!(self.enter(pos));
+                let value = try!(visitor.visit_seq(SeqAccess { de: self }).map_err(|e| e.at(mark)));
+                try!(self.end(pos));
+                Ok(value)
+            },
+            Kind::Mapping => {
+                try!(self.enter(pos));
+                let value = try!(visitor.visit_map(MapAccess { de: self }).map_err(|e| e.at(mark)));
+                try!(self.end(pos));
+                Ok(value)
+            },
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let pos = try!(self.peek());
+        match self.kind(pos) {
+            Kind::Scalar if !self.is_null(pos) => {
+                try!(self.next());
+                let mark = self.items[pos].mark;
+                self.visit_text(pos, visitor).map_err(|e| e.at(mark))
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let pos = try!(self.peek());
+        if self.is_null(pos) {
+            try!(self.next());
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V)
+        -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str,
+                                         _variants: &'static [&'static str], visitor: V)
+        -> Result<V::Value, Error> {
+        let pos = try!(self.peek());
+        let mark = self.items[pos].mark;
+        let tag = match self.items[pos].event {
+            Event::Scalar(_, _, _, ref tag) | Event::SequenceStart(_, ref tag)
+                | Event::MappingStart(_, ref tag) if self.variant_tag != Some(pos) => {
+                local_tag(tag).map(|t| t.to_owned())
+            },
+            _ => None,
+        };
+        if let Some(tag) = tag {
+            self.variant_tag = Some(pos);
+            return visitor.visit_enum(Enum { de: self, variant: Variant::Tag(tag) })
+                .map_err(|e| e.at(mark));
+        }
+        match self.kind(pos) {
+            Kind::Scalar => visitor.visit_enum(Enum { de: self, variant: Variant::Unit })
+                .map_err(|e| e.at(mark)),
+            Kind::Mapping => {
+                try!(self.next());
+                try!(self.enter(pos));
+                let value = try!(visitor.visit_enum(Enum { de: self, variant: Variant::Mapping })
+                    .map_err(|e| e.at(mark)));
+                try!(self.end(pos).map_err(|_| {
+                    Error::new(mark, "expected a mapping with a single entry for an enum")
+                }));
+                Ok(value)
+            },
+            Kind::Sequence => Err(Error::new(mark, "expected a scalar, a mapping or a tagged node for an enum")),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        try!(self.peek());
+        self.skip();
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map struct
+    }
+}
+
+struct SeqAccess<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for SeqAccess<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T)
+        -> Result<Option<T::Value>, Error> {
+        let pos = try!(self.de.peek());
+        match self.de.items[pos].event {
+            Event::SequenceEnd => Ok(None),
+            _ => seed.deserialize(&mut *self.de).map(Some),
+        }
+    }
+}
+
+struct MapAccess<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for MapAccess<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        let pos = try!(self.de.peek());
+        match self.de.items[pos].event {
+            Event::MappingEnd => Ok(None),
+            _ => seed.deserialize(&mut *self.de).map(Some),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+// Where the variant of an enum is named.
+enum Variant {
+    // a plain scalar, for a unit variant
+    Unit,
+    // the key of a single-entry mapping, whose value holds the data
+    Mapping,
+    // the local tag of the node holding the data
+    Tag(String),
+}
+
+struct Enum<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    variant: Variant,
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for Enum<'a, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let value = match self.variant {
+            Variant::Tag(ref tag) => {
+                try!(seed.deserialize(IntoDeserializer::<Error>::into_deserializer(tag.as_str())))
+            },
+            _ => try!(seed.deserialize(&mut *self.de)),
+        };
+        Ok((value, self))
+    }
+}
+
+impl<'de, 'a> de::VariantAccess<'de> for Enum<'a, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self.variant {
+            Variant::Unit => Ok(()),
+            _ => {
+                let pos = try!(self.de.peek());
+                if self.de.is_null(pos) {
+                    try!(self.de.next());
+                    Ok(())
+                } else {
+                    Err(de::Error::invalid_type(Unexpected::Other("a node with data"),
+                                                &"a unit variant"))
+                }
+            },
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        match self.variant {
+            Variant::Unit => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"a newtype variant")),
+            _ => seed.deserialize(self.de),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        match self.variant {
+            Variant::Unit => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"a tuple variant")),
+            _ => de::Deserializer::deserialize_seq(self.de, visitor),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V)
+        -> Result<V::Value, Error> {
+        match self.variant {
+            Variant::Unit => Err(de::Error::invalid_type(Unexpected::UnitVariant, &"a struct variant")),
+            _ => de::Deserializer::deserialize_map(self.de, visitor),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+    use std::collections::BTreeMap;
+    use serde::Deserialize;
+    use super::*;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Server<'a> {
+        name: &'a str,
+        #[serde(borrow)]
+        note: Cow<'a, str>,
+        port: u16,
+        tags: Vec<String>,
+        weight: Option<f64>,
+        backup: Option<Box<Server<'a>>>,
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    enum Action {
+        Stop,
+        Wait(u32),
+        Move(i32, i32),
+        Say { text: String },
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Bytes(Vec<u8>);
+
+    impl<'de> de::Deserialize<'de> for Bytes {
+        fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+            struct BytesVisitor;
+
+            impl<'de> Visitor<'de> for BytesVisitor {
+                type Value = Bytes;
+
+                fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                    formatter.write_str("bytes")
+                }
+
+                fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Bytes, E> {
+                    Ok(Bytes(v))
+                }
+            }
+
+            deserializer.deserialize_byte_buf(BytesVisitor)
+        }
+    }
+
+    #[test]
+    fn test_structs() {
+        let s = "
+defaults: &d {name: base, note: 'it''s', port: 80, tags: [], weight: ~}
+primary:
+  name: \"main\"
+  note: plain text
+  port: 0x1F90
+  tags: [web, 1.10, true]
+  backup: *d
+";
+        let servers: BTreeMap<String, Server> = from_str(s).unwrap();
+        let primary = &servers["primary"];
+        assert_eq!(primary.name, "main");
+        assert_eq!(primary.note, Cow::Borrowed("plain text"));
+        assert_eq!(primary.port, 8080);
+        assert_eq!(primary.tags, vec!["web", "1.10", "true"]);
+        assert_eq!(primary.weight, None);
+        let backup = primary.backup.as_ref().unwrap();
+        assert_eq!(**backup, servers["defaults"]);
+        match backup.note {
+            Cow::Owned(ref note) => assert_eq!(note, "it's"),
+            Cow::Borrowed(_) => panic!("an escaped scalar cannot be borrowed"),
+        }
+
+        assert_eq!(from_str::<(i64, u128, f64, bool, ())>(
+            "[-3, 340282366920938463463374607431768211455, .inf, false, ~]").unwrap(),
+            (-3, u128::max_value(), ::std::f64::INFINITY, false, ()));
+        assert_eq!(from_str::<Bytes>("!!binary aGVsbG8=").unwrap(), Bytes(b"hello".to_vec()));
+        assert_eq!(from_str::<Option<i32>>("").unwrap(), None);
+        assert_eq!(from_str::<String>("!!str 12").unwrap(), "12");
+    }
+
+    #[test]
+    fn test_enums() {
+        let actions: Vec<Action> = from_str("
+- Stop
+- !Wait 5
+- Wait: 6
+- !Move [1, -2]
+- {Move: [3, 4]}
+- !Say {text: hi}
+- Say:
+    text: bye
+- !Stop
+").unwrap();
+        assert_eq!(actions, vec![
+            Action::Stop,
+            Action::Wait(5),
+            Action::Wait(6),
+            Action::Move(1, -2),
+            Action::Move(3, 4),
+            Action::Say { text: "hi".to_owned() },
+            Action::Say { text: "bye".to_owned() },
+            Action::Stop,
+        ]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = from_str::<Vec<Action>>("- Stop\n- Wait").unwrap_err();
+        assert_eq!(err.to_string(), "invalid type: unit variant, expected a newtype variant at line 2 column 3");
+        let err = from_str::<Vec<Action>>("- {Wait: 1, Stop: ~}").unwrap_err();
+        assert_eq!(err.to_string(), "expected a mapping with a single entry for an enum at line 1 column 3");
+
+        let err = from_str::<BTreeMap<String, u16>>("a: 1\nb: 70000").unwrap_err();
+        assert_eq!(err.to_string(),
+                   "invalid value: integer `70000`, expected u16 at line 2 column 4");
+        assert_eq!(err.marker().unwrap().line(), 2);
+        let err = from_str::<Server>("name: x\nport: 1").unwrap_err();
+        assert_eq!(err.to_string(), "missing field `note` at line 1 column 5");
+        let err = from_str::<i64>("!!int x").unwrap_err();
+        assert_eq!(err.to_string(), "invalid value \"x\" for !!int at line 1 column 7");
+        let err = from_str::<i64>("1\n--- 2").unwrap_err();
+        assert_eq!(err.to_string(), "expected a single document at line 2 column 1");
+        let err = from_str::<Vec<i64>>("[1, 2").unwrap_err();
+        assert!(err.to_string().starts_with("while parsing a flow sequence"), "{}", err);
+        assert!(err.marker().is_some());
+        let err = from_str::<Vec<Vec<i64>>>("&a [*a]").unwrap_err();
+        assert_eq!(err.to_string(), "recursive alias at line 1 column 5");
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    #[serde(untagged)]
+    enum Tree {
+        Leaf(String),
+        Node(Vec<Tree>),
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut s = "- &a [lol, lol, lol, lol, lol, lol, lol, lol, lol]\n".to_owned();
+        for (name, prev) in "bcdefgh".chars().zip("abcdefg".chars()) {
+            let refs = vec![format!("*{}", prev); 9].join(", ");
+            s.push_str(&format!("- &{} [{}]\n", name, refs));
+        }
+        let err = from_str::<Vec<Tree>>(&s).unwrap_err();
+        assert_eq!(err.to_string(), "aliases expand the document more than 100 times at line 2 column 7");
+
+        let mut de = Deserializer::new(&s).unwrap();
+        de.max_aliases(20);
+        let err = Vec::<Tree>::deserialize(&mut de).unwrap_err();
+        assert_eq!(err.to_string(), "document exceeds the limit of 20 aliases at line 2 column 7");
+        let mut de = Deserializer::new("- &a [x]\n- *a\n- *a").unwrap();
+        de.max_aliases(2);
+        de.max_alias_ratio(1.0);
+        assert!(Vec::<Tree>::deserialize(&mut de).is_ok());
+
+        let nested = |depth| format!("{}x{}", "[".repeat(depth), "]".repeat(depth));
+        let err = from_str::<Tree>(&nested(129)).unwrap_err();
+        assert_eq!(err.to_string(), "exceeded the maximum nesting depth at line 1 column 129");
+        assert!(from_str::<Tree>(&nested(128)).is_ok());
+        let deep = nested(129);
+        let mut de = Deserializer::with_max_depth(&deep, 129).unwrap();
+        assert!(Tree::deserialize(&mut de).is_ok());
+        assert!(Deserializer::with_max_depth(&deep, 100).is_err());
+
+        // replaying an alias nests its node below the alias
+        let s = format!("- &a {}\n- {}", nested(100), nested(50).replace("x", "*a"));
+        let err = from_str::<Vec<Tree>>(&s).unwrap_err();
+        assert_eq!(err.to_string(), "exceeded the maximum nesting depth at line 1 column 83");
+    }
+}
@@ -0,0 +1,356 @@
+//! Structural differences between two `Yaml` documents.
+//!
+//! `Differ::diff` walks both documents together and reports each node that was
+//! added, removed or changed, by its `Path` from the root. Mappings are compared
+//! key by key, so reordering keys is not a change unless `ordered_mappings` is set,
+//! in which case it is reported as `Change::Reordered`. Sequences are compared
+//! index by index. Floats are compared by value, so `1.0` and `1.00` are equal.
+//!
+//! A `Diff` displays as one line per change, and converts with `to_yaml` into a
+//! sequence of mappings for tools to consume.
+//!
+//! # Examples
+//!
+//! ```
+//! use yaml_rust::YamlLoader;
+//!
+//! let old = YamlLoader::load_from_str("{port: 80, ratio: 1.0, debug: true}").unwrap().remove(0);
+//! let new = YamlLoader::load_from_str("{ratio: 1.00, port: 8080, hosts: [a]}").unwrap().remove(0);
+//! let diff = old.diff(&new);
+//! assert_eq!(diff.to_string(), "\
+//! ~ port: 80 -> 8080
+//! - debug: true
+//! + hosts: [\"a\"]
+//! ");
+//! ```
+
+use std::fmt;
+use emitter::{NonFiniteFloats, OutputFormat, YamlEmitter};
+use query::{Path, PathSegment};
+use yaml::{Hash, Yaml};
+
+/// One difference between two documents.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub enum Change {
+    /// A node that is only in the new document.
+    Added(Path, Yaml),
+    /// A node that is only in the old document.
+    Removed(Path, Yaml),
+    /// A node whose value differs, with its old and new values.
+    Changed(Path, Yaml, Yaml),
+    /// A mapping whose keys appear in a different order, with the keys present in
+    /// both documents in their old and new orders. Only reported by a `Differ` with
+    /// `ordered_mappings` set.
+    Reordered(Path, Vec<Yaml>, Vec<Yaml>),
+}
+
+impl Change {
+    /// The path of the node this change is about.
+    pub fn path(&self) -> &Path {
+        match *self {
+            Change::Added(ref p, _)
+                | Change::Removed(ref p, _)
+                | Change::Changed(ref p, _, _)
+                | Change::Reordered(ref p, _, _) => p,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match *self {
+            Change::Added(..) => "added",
+            Change::Removed(..) => "removed",
+            Change::Changed(..) => "changed",
+            Change::Reordered(..) => "reordered",
+        }
+    }
+}
+
+// A single-line rendering of a node for reports.
+fn inline(node: &Yaml) -> String {
+    if let Yaml::Tagged(ref tag, ref v) = *node {
+        return format!("{} {}", tag, inline(v));
+    }
+    let mut out = String::new();
+    {
+        let mut emitter = YamlEmitter::new(&mut out);
+        emitter.output_format(OutputFormat::JsonCompact);
+        emitter.stringify_keys(true);
+        emitter.non_finite_floats(NonFiniteFloats::String);
+        if emitter.dump(node).is_err() {
+            return format!("{:?}", node);
+        }
+    }
+    out
+}
+
+fn display_path(path: &Path) -> String {
+    if path.is_root() {
+        "(root)".to_owned()
+    } else {
+        path.to_string()
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = display_path(self.path());
+        match *self {
+            Change::Added(_, ref v) => write!(f, "+ {}: {}", path, inline(v)),
+            Change::Removed(_, ref v) => write!(f, "- {}: {}", path, inline(v)),
+            Change::Changed(_, ref old, ref new) => write!(f, "~ {}: {} -> {}", path, inline(old), inline(new)),
+            Change::Reordered(_, ref old, ref new) => {
+                write!(f, "~ {}: keys reordered from {} to {}", path,
+                       inline(&Yaml::Array(old.clone())), inline(&Yaml::Array(new.clone())))
+            },
+        }
+    }
+}
+
+/// The changes between two documents, in document order.
+#[derive(Clone, PartialEq, Debug, Eq, Default)]
+pub struct Diff {
+    changes: Vec<Change>,
+}
+
+impl Diff {
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The changes as a sequence of mappings, one per change, e.g.
+    /// `{op: changed, path: servers[0].port, old: 80, new: 8080}`. Added nodes have
+    /// only `new` and removed nodes only `old`; reordered mappings hold their key
+    /// lists under `old` and `new`.
+    pub fn to_yaml(&self) -> Yaml {
+        let mut report = Vec::new();
+        for change in &self.changes {
+            let mut h = Hash::new();
+            h.insert(Yaml::String("op".to_owned()), Yaml::String(change.kind().to_owned()));
+            h.insert(Yaml::String("path".to_owned()), Yaml::String(change.path().to_string()));
+            let (old, new) = match *change {
+                Change::Added(_, ref v) => (None, Some(v.clone())),
+                Change::Removed(_, ref v) => (Some(v.clone()), None),
+                Change::Changed(_, ref old, ref new) => (Some(old.clone()), Some(new.clone())),
+                Change::Reordered(_, ref old, ref new) => {
+                    (Some(Yaml::Array(old.clone())), Some(Yaml::Array(new.clone())))
+                },
+            };
+            if let Some(old) = old {
+                h.insert(Yaml::String("old".to_owned()), old);
+            }
+            if let Some(new) = new {
+                h.insert(Yaml::String("new".to_owned()), new);
+            }
+            report.push(Yaml::Hash(h));
+        }
+        Yaml::Array(report)
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.changes {
+            try!(writeln!(f, "{}", change));
+        }
+        Ok(())
+    }
+}
+
+/// Computes a `Diff` between two documents.
+#[derive(Clone, Copy, PartialEq, Debug, Eq, Default)]
+pub struct Differ {
+    ordered: bool,
+}
+
+// Whether two floats written as `a` and `b` have the same value. NaN equals NaN.
+fn same_real(a: &str, b: &str) -> bool {
+    match (Yaml::Real(a.to_owned()).as_f64(), Yaml::Real(b.to_owned()).as_f64()) {
+        (Some(x), Some(y)) => x == y || (x.is_nan() && y.is_nan()),
+        _ => a == b,
+    }
+}
+
+impl Differ {
+    pub fn new() -> Differ {
+        Differ { ordered: false }
+    }
+
+    /// Report mappings whose common keys appear in a different order as
+    /// `Change::Reordered`. Off by default.
+    pub fn ordered_mappings(&mut self, ordered: bool) {
+        self.ordered = ordered;
+    }
+
+    pub fn diff(&self, old: &Yaml, new: &Yaml) -> Diff {
+        let mut changes = Vec::new();
+        self.diff_node(&mut Path::new(), old, new, &mut changes);
+        Diff { changes: changes }
+    }
+
+    fn diff_node(&self, path: &mut Path, old: &Yaml, new: &Yaml, changes: &mut Vec<Change>) {
+        match (old, new) {
+            (&Yaml::Real(ref a), &Yaml::Real(ref b)) if same_real(a, b) => {},
+            (&Yaml::Hash(ref a), &Yaml::Hash(ref b))
+                | (&Yaml::OrderedMap(ref a), &Yaml::OrderedMap(ref b)) => {
+                self.diff_mapping(path, a, b, changes);
+            },
+            (&Yaml::Set(ref a), &Yaml::Set(ref b)) => {
+                if self.ordered {
+                    reorders(path, a.keys(), b.keys(), |k| a.contains_key(k) && b.contains_key(k), changes);
+                }
+                for k in a.keys().filter(|k| !b.contains_key(k)) {
+                    changes.push(Change::Removed(path.child(PathSegment::Key(k.clone())), k.clone()));
+                }
+                for k in b.keys().filter(|k| !a.contains_key(k)) {
+                    changes.push(Change::Added(path.child(PathSegment::Key(k.clone())), k.clone()));
+                }
+            },
+            (&Yaml::Array(ref a), &Yaml::Array(ref b)) => {
+                for (i, (x, y)) in a.iter().zip(b.iter()).enumerate() {
+                    path.push(PathSegment::Index(i));
+                    self.diff_node(path, x, y, changes);
+                    path.pop();
+                }
+                for (i, x) in a.iter().enumerate().skip(b.len()) {
+                    changes.push(Change::Removed(path.child(PathSegment::Index(i)), x.clone()));
+                }
+                for (i, y) in b.iter().enumerate().skip(a.len()) {
+                    changes.push(Change::Added(path.child(PathSegment::Index(i)), y.clone()));
+                }
+            },
+            (&Yaml::Tagged(ref s, ref a), &Yaml::Tagged(ref t, ref b)) if s == t => {
+                self.diff_node(path, a, b, changes);
+            },
+            _ if old == new => {},
+            _ => changes.push(Change::Changed(path.clone(), old.clone(), new.clone())),
+        }
+    }
+
+    fn diff_mapping(&self, path: &mut Path, old: &Hash, new: &Hash, changes: &mut Vec<Change>) {
+        if self.ordered {
+            reorders(path, old.keys(), new.keys(), |k| old.contains_key(k) && new.contains_key(k), changes);
+        }
+        for (k, v) in old {
+            path.push(PathSegment::Key(k.clone()));
+            match new.get(k) {
+                Some(w) => self.diff_node(path, v, w, changes),
+                None => changes.push(Change::Removed(path.clone(), v.clone())),
+            }
+            path.pop();
+        }
+        for (k, w) in new {
+            if !old.contains_key(k) {
+                changes.push(Change::Added(path.child(PathSegment::Key(k.clone())), w.clone()));
+            }
+        }
+    }
+}
+
+// Record a `Reordered` change if the keys kept by `common` come in a different order.
+fn reorders<'a, I, F>(path: &Path, old: I, new: I, common: F, changes: &mut Vec<Change>)
+    where I: Iterator<Item=&'a Yaml>, F: Fn(&Yaml) -> bool {
+    let old: Vec<Yaml> = old.filter(|k| common(k)).cloned().collect();
+    let new: Vec<Yaml> = new.filter(|k| common(k)).cloned().collect();
+    if old != new {
+        changes.push(Change::Reordered(path.clone(), old, new));
+    }
+}
+
+impl Yaml {
+    /// The changes from this document to `other`, ignoring the order of mapping
+    /// keys. See `Differ` to respect it.
+    pub fn diff(&self, other: &Yaml) -> Diff {
+        Differ::new().diff(self, other)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Change, Differ};
+    use query::{Path, PathSegment};
+    use yaml::{Yaml, YamlLoader};
+
+    fn load(s: &str) -> Yaml {
+        let mut loader = YamlLoader::new();
+        loader.preserve_tags(true);
+        loader.load(s).unwrap().remove(0)
+    }
+
+    fn path(keys: &[&str]) -> Path {
+        let mut path = Path::new();
+        for k in keys {
+            match k.parse::<usize>() {
+                Ok(i) => path.push(PathSegment::Index(i)),
+                Err(_) => path.push(PathSegment::Key(Yaml::String((*k).to_owned()))),
+            }
+        }
+        path
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = load("
+servers:
+  - {host: a, port: 80}
+  - {host: b, port: 81}
+limits: {cpu: 0.5, memory: 1e3}
+tags: !!set {x, y}
+name: !Name web
+");
+        let new = load("
+name: !Name web
+limits: {memory: 1000.0, cpu: 0.50}
+servers:
+  - {port: 8080, host: a}
+tags: !!set {y, z}
+");
+        assert!(old.diff(&old).is_empty());
+        let diff = old.diff(&new);
+        assert_eq!(diff.changes(), &[
+            Change::Changed(path(&["servers", "0", "port"]), Yaml::Integer(80), Yaml::Integer(8080)),
+            Change::Removed(path(&["servers", "1"]), load("{host: b, port: 81}")),
+            Change::Removed(path(&["tags", "x"]), Yaml::String("x".to_owned())),
+            Change::Added(path(&["tags", "z"]), Yaml::String("z".to_owned())),
+        ][..]);
+
+        assert_eq!(load("1").diff(&load("1.0")).changes(),
+                   &[Change::Changed(Path::new(), Yaml::Integer(1), Yaml::Real("1.0".to_owned()))][..]);
+        assert!(load(".nan").diff(&load(".NaN")).is_empty());
+        assert!(load("!A 1").diff(&load("!B 1")).changes()[0].path().is_root());
+    }
+
+    #[test]
+    fn test_ordered() {
+        let old = load("{a: 1, b: 2, c: 3}");
+        let new = load("{c: 3, b: 2, d: 4}");
+        let mut differ = Differ::new();
+        assert_eq!(differ.diff(&old, &new).changes().len(), 2);
+        differ.ordered_mappings(true);
+        let diff = differ.diff(&old, &new);
+        assert_eq!(diff.changes()[0], Change::Reordered(Path::new(), vec![load("b"), load("c")], vec![load("c"), load("b")]));
+        assert_eq!(diff.to_string(), "\
+~ (root): keys reordered from [\"b\",\"c\"] to [\"c\",\"b\"]
+- a: 1
++ d: 4
+");
+    }
+
+    #[test]
+    fn test_report() {
+        let diff = load("{a: [1, 2], b: x}").diff(&load("{a: [1], b: !T y, \"c d\": {e: null}}"));
+        assert_eq!(diff.to_string(), "\
+- a[1]: 2
+~ b: \"x\" -> !T \"y\"
++ [\"c d\"]: {\"e\":null}
+");
+        assert_eq!(diff.to_yaml(), load("
+- {op: removed, path: 'a[1]', old: 2}
+- {op: changed, path: b, old: x, new: !T y}
+- {op: added, path: '[\"c d\"]', new: {e: null}}
+"));
+    }
+}
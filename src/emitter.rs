@@ -1,13 +1,22 @@
 use std::fmt::{self, Display};
 use std::convert::From;
 use std::error::Error;
-use yaml::{Hash, Yaml};
+use std::collections::HashSet;
+use std::{cmp, str};
+use scanner::is_json_number;
+use yaml::{AnchorMap, Hash, Tag, Yaml};
+use base64;
+
+// Length of the base64 lines written for `Yaml::Binary`, as in MIME.
+const BINARY_LINE_WIDTH: usize = 76;
 
 
 #[derive(Copy, Clone, Debug)]
 pub enum EmitError {
         FmtError(fmt::Error),
         BadHashmapKey,
+        DepthLimitExceeded,
+        NonFiniteFloat,
 }
 
 impl Error for EmitError {
@@ -15,6 +24,8 @@ impl Error for EmitError {
         match *self {
             EmitError::FmtError(ref err) => err.description(),
             EmitError::BadHashmapKey => "bad hashmap key",
+            EmitError::DepthLimitExceeded => "exceeded the maximum nesting depth",
+            EmitError::NonFiniteFloat => "non-finite float in JSON output",
         }
     }
 
@@ -28,6 +39,8 @@ impl Display for EmitError {
         match *self {
             EmitError::FmtError(ref err) => Display::fmt(err, formatter),
             EmitError::BadHashmapKey => formatter.write_str("bad hashmap key"),
+            EmitError::DepthLimitExceeded => formatter.write_str("exceeded the maximum nesting depth"),
+            EmitError::NonFiniteFloat => formatter.write_str("non-finite float in JSON output"),
         }
     }
 }
@@ -38,12 +51,43 @@ impl From<fmt::Error> for EmitError {
     }
 }
 
+/// The text format written by a `YamlEmitter`.
+#[derive(Clone, Copy, PartialEq, Debug, Eq)]
+pub enum OutputFormat {
+    /// A YAML document starting with `---` (the default).
+    Yaml,
+    /// Indented JSON, with one value or member per line.
+    Json,
+    /// JSON without any whitespace.
+    JsonCompact,
+}
+
+/// How `.inf`, `-.inf` and `.nan` values are written as JSON, which has no
+/// representation for them.
+#[derive(Clone, Copy, PartialEq, Debug, Eq)]
+pub enum NonFiniteFloats {
+    /// Fail with `EmitError::NonFiniteFloat`.
+    Reject,
+    /// Write `null`, as JavaScript's `JSON.stringify` does (the default).
+    Null,
+    /// Write the value as a string, e.g. `".inf"`.
+    String,
+}
+
 pub struct YamlEmitter<'a> {
     writer: &'a mut fmt::Write,
     best_indent: usize,
     compact: bool,
+    max_depth: Option<usize>,
+    format: OutputFormat,
+    stringify_keys: bool,
+    non_finite_floats: NonFiniteFloats,
+    anchors: Option<&'a AnchorMap>,
 
     level: isize,
+    depth: usize,
+    // ids of the anchors already written in the current document
+    written_anchors: HashSet<usize>,
 }
 
 pub type EmitResult = Result<(), EmitError>;
@@ -117,8 +161,15 @@ impl<'a> YamlEmitter<'a> {
             writer: writer,
             best_indent: 2,
             compact: true,
+            max_depth: None,
+            format: OutputFormat::Yaml,
+            stringify_keys: false,
+            non_finite_floats: NonFiniteFloats::Null,
+            anchors: None,
 
-            level: -1
+            level: -1,
+            depth: 0,
+            written_anchors: HashSet::new(),
         }
     }
 
@@ -139,13 +190,103 @@ impl<'a> YamlEmitter<'a> {
       self.compact
     }
 
+    /// Limit how deeply sequences and mappings may be nested in a dumped document.
+    /// Deeper documents fail with `EmitError::DepthLimitExceeded` instead of
+    /// overflowing the stack. Unlimited by default.
+    pub fn max_depth(&mut self, limit: usize) {
+        self.max_depth = Some(limit);
+    }
+
+    /// Choose between YAML output (the default) and pretty or compact JSON.
+    ///
+    /// JSON keeps the order of mappings. Tags are dropped, timestamps are written
+    /// in their canonical form and binary data in base64, as strings. Sets,
+    /// ordered maps and pairs are written like their `!!set`, `!!omap` and
+    /// `!!pairs` YAML forms.
+    ///
+    /// ```
+    /// use yaml_rust::{YamlEmitter, YamlLoader};
+    /// use yaml_rust::emitter::OutputFormat;
+    ///
+    /// let docs = YamlLoader::load_from_str("{b: [1, .5], a: null}").unwrap();
+    /// let mut out = String::new();
+    /// {
+    ///     let mut emitter = YamlEmitter::new(&mut out);
+    ///     emitter.output_format(OutputFormat::JsonCompact);
+    ///     emitter.dump(&docs[0]).unwrap();
+    /// }
+    /// assert_eq!(out, r#"{"b":[1,0.5],"a":null}"#);
+    /// ```
+    pub fn output_format(&mut self, format: OutputFormat) {
+        self.format = format;
+    }
+
+    /// Write mapping keys that are not strings as JSON strings, e.g. `1` as
+    /// `"1"` and `[a, b]` as `"[\"a\",\"b\"]"`, instead of failing with
+    /// `EmitError::BadHashmapKey`. Only applies to JSON output.
+    pub fn stringify_keys(&mut self, stringify: bool) {
+        self.stringify_keys = stringify;
+    }
+
+    /// Set how infinite and NaN floats are written as JSON. Only applies to
+    /// JSON output.
+    pub fn non_finite_floats(&mut self, policy: NonFiniteFloats) {
+        self.non_finite_floats = policy;
+    }
+
+    /// Write each `Yaml::Alias` with the anchored node it refers to in `anchors`,
+    /// as loaded by a `YamlLoader` with alias expansion disabled.
+    ///
+    /// In YAML output, the first alias to each node is written as the node with
+    /// an anchor, e.g. `&a1 [x, y]`, and later ones as `*a1`, so the dumped
+    /// document loads back with the same sharing. JSON output, which has no
+    /// aliases, writes a copy of the node every time. Without anchors, or for an
+    /// id missing from them, an alias is written as null.
+    ///
+    /// ```
+    /// use yaml_rust::{YamlEmitter, YamlLoader};
+    ///
+    /// let mut loader = YamlLoader::new();
+    /// loader.expand_aliases(false);
+    /// let docs = loader.load("a: &x [1, 2]\nb: *x\nc: *x").unwrap();
+    /// let mut out = String::new();
+    /// {
+    ///     let mut emitter = YamlEmitter::new(&mut out);
+    ///     emitter.anchors(loader.anchors());
+    ///     emitter.dump(&docs[0]).unwrap();
+    /// }
+    /// assert_eq!(out, "---\na:\n  - 1\n  - 2\nb: &a1\n  - 1\n  - 2\nc: *a1");
+    /// ```
+    pub fn anchors(&mut self, anchors: &'a AnchorMap) {
+        self.anchors = Some(anchors);
+    }
+
+    // The node that alias `id` refers to, unless the anchors do not have it.
+    fn alias_target(&self, id: usize) -> Option<&'a Yaml> {
+        self.anchors.and_then(|a| a.get(&id))
+    }
+
     pub fn dump(&mut self, doc: &Yaml) -> EmitResult {
+        self.level = -1;
+        self.depth = 0;
+        self.written_anchors.clear();
+        if self.format != OutputFormat::Yaml {
+            self.level = 0;
+            return self.emit_json(doc);
+        }
         // write DocumentStart
         try!(write!(self.writer, "---\n"));
-        self.level = -1;
         self.emit_node(doc)
     }
 
+    fn enter_collection(&mut self) -> EmitResult {
+        self.depth += 1;
+        match self.max_depth {
+            Some(limit) if self.depth > limit => Err(EmitError::DepthLimitExceeded),
+            _ => Ok(()),
+        }
+    }
+
     fn write_indent(&mut self) -> EmitResult {
         if self.level <= 0 { return Ok(()); }
         for _ in 0..self.level {
@@ -181,20 +322,58 @@ impl<'a> YamlEmitter<'a> {
                 try!(write!(self.writer, "{}", v));
                 Ok(())
             },
-            Yaml::Real(ref v) => {
+            Yaml::Real(ref v) | Yaml::BigInteger(ref v) => {
+                try!(write!(self.writer, "{}", v));
+                Ok(())
+            },
+            Yaml::Timestamp(ref v) => {
                 try!(write!(self.writer, "{}", v));
                 Ok(())
             },
+            Yaml::Binary(ref v) => self.emit_binary(v),
+            Yaml::Set(_) | Yaml::OrderedMap(_) | Yaml::Pairs(_) => {
+                self.emit_node(&tagged_raw_form(node))
+            },
             Yaml::Null | Yaml::BadValue => {
                 try!(write!(self.writer, "~"));
                 Ok(())
             },
-            // XXX(chenyh) Alias
-            _ => { Ok(()) }
+            Yaml::Tagged(ref tag, ref v) => {
+                try!(write!(self.writer, "{}", tag));
+                self.emit_val(false, v)
+            },
+            Yaml::Alias(id) => match self.alias_target(id) {
+                None => {
+                    try!(write!(self.writer, "~"));
+                    Ok(())
+                },
+                Some(_) if self.written_anchors.contains(&id) => {
+                    try!(write!(self.writer, "*a{}", id));
+                    Ok(())
+                },
+                Some(target) => {
+                    self.written_anchors.insert(id);
+                    try!(write!(self.writer, "&a{}", id));
+                    self.emit_val(false, target)
+                },
+            },
         }
     }
 
+    /// Emit bytes as a `!!binary` literal block of base64 lines, indented one
+    /// level deeper than the current node.
+    fn emit_binary(&mut self, v: &[u8]) -> EmitResult {
+        try!(write!(self.writer, "!!binary |"));
+        let encoded = base64::encode(v);
+        let indent = " ".repeat(cmp::max(self.level + 1, 1) as usize * self.best_indent);
+        for line in encoded.as_bytes().chunks(BINARY_LINE_WIDTH) {
+            try!(write!(self.writer, "\n{}{}", indent, str::from_utf8(line).unwrap()));
+        }
+        Ok(())
+    }
+
     fn emit_array(&mut self, v: &[Yaml]) -> EmitResult {
+        try!(self.enter_collection());
         if v.is_empty() {
             try!(write!(self.writer, "[]"));
         } else {
@@ -209,17 +388,22 @@ impl<'a> YamlEmitter<'a> {
             }
             self.level -= 1;
         }
+        self.depth -= 1;
         Ok(())
     }
 
     fn emit_hash(&mut self, h: &Hash) -> EmitResult {
+        try!(self.enter_collection());
         if h.is_empty() {
             try!(self.writer.write_str("{}"));
         } else {
             self.level += 1;
             for (cnt, (k, v)) in h.iter().enumerate() {
-                let complex_key = match *k {
-                  Yaml::Hash(_) | Yaml::Array(_) => true,
+                let complex_key = match *k.untagged() {
+                  Yaml::Hash(_) | Yaml::Array(_) | Yaml::Binary(_) => true,
+                  Yaml::Set(_) | Yaml::OrderedMap(_) | Yaml::Pairs(_) => true,
+                  // `*a1:` would read as an alias named `a1:`
+                  Yaml::Alias(_) => true,
                   _ => false,
                 };
                 if cnt > 0 {
@@ -241,6 +425,7 @@ impl<'a> YamlEmitter<'a> {
             }
             self.level -= 1;
         }
+        self.depth -= 1;
         Ok(())
     }
 
@@ -272,6 +457,13 @@ impl<'a> YamlEmitter<'a> {
                 }
                 self.emit_hash(h)
             },
+            Yaml::Tagged(ref tag, ref v) => {
+                try!(write!(self.writer, " {}", tag));
+                self.emit_val(false, v)
+            },
+            Yaml::Set(_) | Yaml::OrderedMap(_) | Yaml::Pairs(_) => {
+                self.emit_val(inline, &tagged_raw_form(val))
+            },
             _ => {
                 try!(write!(self.writer, " "));
                 self.emit_node(val)
@@ -280,6 +472,153 @@ impl<'a> YamlEmitter<'a> {
     }
 }
 
+// JSON output
+impl<'a> YamlEmitter<'a> {
+    fn emit_json(&mut self, node: &Yaml) -> EmitResult {
+        match *node {
+            Yaml::Array(ref v) => self.emit_json_array(v),
+            Yaml::Hash(ref h) => self.emit_json_object(h),
+            Yaml::String(ref v) => Ok(try!(escape_str(self.writer, v))),
+            Yaml::Boolean(v) => Ok(try!(write!(self.writer, "{}", v))),
+            Yaml::Integer(v) => Ok(try!(write!(self.writer, "{}", v))),
+            Yaml::BigInteger(ref v) => Ok(try!(self.writer.write_str(v))),
+            Yaml::Real(ref v) => self.emit_json_number(v, node.as_f64()),
+            Yaml::Timestamp(ref v) => Ok(try!(escape_str(self.writer, &v.to_string()))),
+            Yaml::Binary(ref v) => Ok(try!(escape_str(self.writer, &base64::encode(v)))),
+            Yaml::Set(_) | Yaml::OrderedMap(_) | Yaml::Pairs(_) => {
+                self.emit_json(tagged_raw_form(node).untagged())
+            },
+            Yaml::Tagged(_, ref v) => self.emit_json(v),
+            Yaml::Alias(id) if self.alias_target(id).is_some() => {
+                let target = self.alias_target(id).unwrap();
+                self.emit_json(target)
+            },
+            Yaml::Null | Yaml::BadValue | Yaml::Alias(_) => Ok(try!(self.writer.write_str("null"))),
+        }
+    }
+
+    fn emit_json_number(&mut self, text: &str, value: Option<f64>) -> EmitResult {
+        match value {
+            Some(v) if v.is_finite() => {
+                if is_json_number(text) {
+                    try!(self.writer.write_str(text));
+                } else {
+                    // e.g. `+1.5` or `.5`, which JSON does not allow
+                    try!(write!(self.writer, "{:?}", v));
+                }
+            },
+            _ => match self.non_finite_floats {
+                NonFiniteFloats::Reject => return Err(EmitError::NonFiniteFloat),
+                NonFiniteFloats::Null => try!(self.writer.write_str("null")),
+                NonFiniteFloats::String => try!(escape_str(self.writer, text)),
+            },
+        }
+        Ok(())
+    }
+
+    // Start a new line at the current level of a pretty-printed JSON document.
+    fn json_newline(&mut self) -> EmitResult {
+        if self.format == OutputFormat::Json {
+            try!(write!(self.writer, "\n"));
+            for _ in 0..self.level as usize * self.best_indent {
+                try!(self.writer.write_str(" "));
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_json_array(&mut self, v: &[Yaml]) -> EmitResult {
+        try!(self.enter_collection());
+        try!(self.writer.write_str("["));
+        if !v.is_empty() {
+            self.level += 1;
+            for (cnt, x) in v.iter().enumerate() {
+                if cnt > 0 {
+                    try!(self.writer.write_str(","));
+                }
+                try!(self.json_newline());
+                try!(self.emit_json(x));
+            }
+            self.level -= 1;
+            try!(self.json_newline());
+        }
+        try!(self.writer.write_str("]"));
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn emit_json_object(&mut self, h: &Hash) -> EmitResult {
+        try!(self.enter_collection());
+        try!(self.writer.write_str("{"));
+        if !h.is_empty() {
+            self.level += 1;
+            for (cnt, (k, v)) in h.iter().enumerate() {
+                if cnt > 0 {
+                    try!(self.writer.write_str(","));
+                }
+                try!(self.json_newline());
+                try!(self.emit_json_key(k));
+                if self.format == OutputFormat::Json {
+                    try!(self.writer.write_str(": "));
+                } else {
+                    try!(self.writer.write_str(":"));
+                }
+                try!(self.emit_json(v));
+            }
+            self.level -= 1;
+            try!(self.json_newline());
+        }
+        try!(self.writer.write_str("}"));
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn emit_json_key(&mut self, k: &Yaml) -> EmitResult {
+        let key = match *k.untagged() {
+            Yaml::String(ref k) => return Ok(try!(escape_str(self.writer, k))),
+            _ if !self.stringify_keys => return Err(EmitError::BadHashmapKey),
+            Yaml::Real(ref v) | Yaml::BigInteger(ref v) => v.clone(),
+            Yaml::Integer(v) => v.to_string(),
+            Yaml::Boolean(v) => v.to_string(),
+            Yaml::Timestamp(ref v) => v.to_string(),
+            Yaml::Null | Yaml::BadValue | Yaml::Alias(_) => "null".to_owned(),
+            ref k => {
+                // collections and binary data become their compact JSON text
+                let mut text = String::new();
+                {
+                    let mut emitter = YamlEmitter::new(&mut text);
+                    emitter.output_format(OutputFormat::JsonCompact);
+                    emitter.stringify_keys(true);
+                    emitter.non_finite_floats(self.non_finite_floats);
+                    try!(emitter.emit_json(k));
+                }
+                text
+            },
+        };
+        Ok(try!(escape_str(self.writer, &key)))
+    }
+}
+
+/// The `!!set`, `!!omap` or `!!pairs` tagged collection that a `Yaml::Set`,
+/// `Yaml::OrderedMap` or `Yaml::Pairs` is written as.
+fn tagged_raw_form(node: &Yaml) -> Yaml {
+    fn single_pairs<'a, I: Iterator<Item=(&'a Yaml, &'a Yaml)>>(pairs: I) -> Yaml {
+        Yaml::Array(pairs.map(|(k, v)| {
+            let mut h = Hash::new();
+            h.insert(k.clone(), v.clone());
+            Yaml::Hash(h)
+        }).collect())
+    }
+
+    let (suffix, raw) = match *node {
+        Yaml::Set(ref s) => ("set", Yaml::Hash(s.keys().map(|k| (k.clone(), Yaml::Null)).collect())),
+        Yaml::OrderedMap(ref h) => ("omap", single_pairs(h.iter())),
+        Yaml::Pairs(ref p) => ("pairs", single_pairs(p.iter().map(|&(ref k, ref v)| (k, v)))),
+        _ => unreachable!(),
+    };
+    Yaml::Tagged(Tag::new("!!", suffix), Box::new(raw))
+}
+
 /// Check if the string requires quoting.
 /// Strings containing any of the following characters must be quoted.
 /// :, {, }, [, ], ,, &, *, #, ?, |, -, <, >, =, !, %, @, `
@@ -291,7 +630,7 @@ impl<'a> YamlEmitter<'a> {
 /// * When the string is true or false (otherwise, it would be treated as a boolean value);
 /// * When the string is null or ~ (otherwise, it would be considered as a null value);
 /// * When the string looks like a number, such as integers (e.g. 2, 14, etc.), floats (e.g. 2.6, 14.9) and exponential numbers (e.g. 12e7, etc.) (otherwise, it would be treated as a numeric value);
-/// * When the string looks like a date (e.g. 2014-12-31) (otherwise it would be automatically converted into a Unix timestamp).
+/// * When the string looks like a date (e.g. 2014-12-31) (otherwise it would be loaded as a timestamp).
 fn need_quotes(string: &str) -> bool {
     fn need_quotes_spaces(string: &str) -> bool {
         string.starts_with(' ')
@@ -612,4 +951,266 @@ a:
         assert_eq!(s, writer);
     }
 
+    #[test]
+    fn test_max_depth() {
+        let mut doc = Yaml::Null;
+        for _ in 0..200 {
+            let mut h = Hash::new();
+            h.insert(Yaml::String("a".to_owned()), Yaml::Array(vec![doc]));
+            doc = Yaml::Hash(h);
+        }
+        let mut writer = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.max_depth(400);
+            emitter.dump(&doc).unwrap();
+            emitter.max_depth(399);
+            match emitter.dump(&doc) {
+                Err(EmitError::DepthLimitExceeded) => {},
+                r => panic!("unexpected result: {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_emit_tags() {
+        let s = r#"
+bucket: !Ref MyBucket
+arn: !GetAtt [MyBucket, Arn]
+props: !Custom {a: 1, "b c": !Sub "${x}"}
+? !Key [1, 2]
+: !!str 3
+verbatim: !<tag:example.com,2000:x> y
+"#;
+        let mut loader = YamlLoader::new();
+        loader.preserve_tags(true);
+        let docs = loader.load(&s).unwrap();
+        let doc = &docs[0];
+        let mut writer = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.dump(doc).unwrap();
+        }
+        println!("emitted:\n{}", writer);
+        let docs_new = loader.load(&writer).unwrap();
+        assert_eq!(doc, &docs_new[0]);
+        assert!(writer.contains("bucket: !Ref MyBucket"));
+
+        let top = Yaml::Tagged(Tag::new("!", "Top"), Box::new(doc.clone()));
+        let mut writer = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.dump(&top).unwrap();
+        }
+        assert_eq!(loader.load(&writer).unwrap()[0], top);
+    }
+
+    #[test]
+    fn test_emit_timestamps() {
+        let s = "
+a: 2001-12-14t21:59:43.10-05:00
+b: 2002-12-14
+c: '2002-12-14'
+";
+        let docs = YamlLoader::load_from_str(&s).unwrap();
+        let mut writer = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.dump(&docs[0]).unwrap();
+        }
+        assert_eq!(writer, "---\na: 2001-12-15T02:59:43.1Z\nb: 2002-12-14\nc: \"2002-12-14\"");
+        let docs_new = YamlLoader::load_from_str(&writer).unwrap();
+        assert_eq!(docs_new[0]["a"], Yaml::Timestamp(docs[0]["a"].as_timestamp().unwrap().to_utc()));
+    }
+
+    #[test]
+    fn test_emit_binary() {
+        let data: Vec<u8> = (0..100).collect();
+        let mut h = Hash::new();
+        h.insert(Yaml::String("data".to_owned()), Yaml::Binary(data.clone()));
+        h.insert(Yaml::String("list".to_owned()), Yaml::Array(vec![Yaml::Binary(b"hi".to_vec())]));
+        h.insert(Yaml::Binary(b"key".to_vec()), Yaml::Binary(Vec::new()));
+        let doc = Yaml::Hash(h);
+        let mut writer = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.dump(&doc).unwrap();
+        }
+        println!("emitted:\n{}", writer);
+        assert!(writer.starts_with("---\ndata: !!binary |\n  AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4\n  OTo7"));
+        let docs_new = YamlLoader::load_from_str(&writer).unwrap();
+        assert_eq!(docs_new[0], doc);
+
+        for top in &[Yaml::Binary(data), Yaml::Array(vec![Yaml::Binary(b"x".to_vec())])] {
+            let mut writer = String::new();
+            {
+                let mut emitter = YamlEmitter::new(&mut writer);
+                emitter.dump(top).unwrap();
+            }
+            assert_eq!(&YamlLoader::load_from_str(&writer).unwrap()[0], top);
+        }
+    }
+
+    #[test]
+    fn test_emit_typed_collections() {
+        let s = "
+set: !!set {a, b, [1, 2]}
+omap: !!omap
+- z: 1
+- a: {x: 2}
+pairs: !!pairs [{a: 1}, {a: 2}]
+empty: !!set {}
+nested:
+- !!omap []
+- !!pairs
+  - ? !!set {x}
+    : y
+";
+        let docs = YamlLoader::load_from_str(&s).unwrap();
+        let doc = &docs[0];
+        let mut writer = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.dump(doc).unwrap();
+        }
+        println!("emitted:\n{}", writer);
+        let docs_new = YamlLoader::load_from_str(&writer).unwrap();
+        assert_eq!(doc, &docs_new[0]);
+        assert!(writer.contains("omap: !!omap\n  - z: 1"));
+    }
+
+
+    fn json(doc: &Yaml, format: OutputFormat) -> Result<String, EmitError> {
+        let mut writer = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.output_format(format);
+            try!(emitter.dump(doc));
+        }
+        Ok(writer)
+    }
+
+    #[test]
+    fn test_emit_json() {
+        let s = r#"
+z: last key first
+list: [1, -2.5e3, +1.5, .5, 12345678901234567890, true, ~, "two\nlines"]
+empty: {list: [], map: {}}
+nested:
+  - {a: 1}
+  - [x]
+when: 2001-12-14 21:59:43.10 -5
+tagged: !Ref Bucket
+set: !!set {a, b}
+"#;
+        let docs = YamlLoader::load_from_str(s).unwrap();
+        assert_eq!(json(&docs[0], OutputFormat::JsonCompact).unwrap(),
+                   r#"{"z":"last key first","list":[1,-2.5e3,1.5,0.5,12345678901234567890,true,null,"two\nlines"],"#
+                   .to_owned() + r#""empty":{"list":[],"map":{}},"nested":[{"a":1},["x"]],"#
+                   + r#""when":"2001-12-15T02:59:43.1Z","tagged":"Bucket","set":{"a":null,"b":null}}"#);
+        assert_eq!(json(&docs[0]["nested"], OutputFormat::Json).unwrap(), r#"[
+  {
+    "a": 1
+  },
+  [
+    "x"
+  ]
+]"#);
+        assert_eq!(json(&docs[0]["empty"], OutputFormat::Json).unwrap(), r#"{
+  "list": [],
+  "map": {}
+}"#);
+        assert_eq!(json(&Yaml::Integer(1), OutputFormat::Json).unwrap(), "1");
+    }
+
+    #[test]
+    fn test_emit_json_policies() {
+        let docs = YamlLoader::load_from_str("{1: .inf, [a, 2]: -.inf, ~: .nan, true: 0.1}").unwrap();
+        let mut writer = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.output_format(OutputFormat::JsonCompact);
+            match emitter.dump(&docs[0]) {
+                Err(EmitError::BadHashmapKey) => {},
+                r => panic!("unexpected result: {:?}", r),
+            }
+            emitter.stringify_keys(true);
+            emitter.non_finite_floats(NonFiniteFloats::Reject);
+            match emitter.dump(&docs[0]) {
+                Err(EmitError::NonFiniteFloat) => {},
+                r => panic!("unexpected result: {:?}", r),
+            }
+        }
+        writer.clear();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.output_format(OutputFormat::JsonCompact);
+            emitter.stringify_keys(true);
+            emitter.dump(&docs[0]).unwrap();
+        }
+        assert_eq!(writer, r#"{"1":null,"[\"a\",2]":null,"null":null,"true":0.1}"#);
+        writer.clear();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.output_format(OutputFormat::JsonCompact);
+            emitter.stringify_keys(true);
+            emitter.non_finite_floats(NonFiniteFloats::String);
+            emitter.dump(&docs[0]).unwrap();
+        }
+        assert_eq!(writer, r#"{"1":".inf","[\"a\",2]":"-.inf","null":".nan","true":0.1}"#);
+    }
+
+    #[test]
+    fn test_emit_anchors() {
+        let s = "
+a: &a [x, {y: 1}]
+b: &b [*a, *a]
+c: [*b, *b, *a]
+d: {k: *a}
+";
+        let mut loader = YamlLoader::new();
+        loader.expand_aliases(false);
+        let docs = loader.load(s).unwrap();
+        let expanded = YamlLoader::load_from_str(s).unwrap();
+
+        let mut writer = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.anchors(loader.anchors());
+            emitter.dump(&docs[0]).unwrap();
+        }
+        writer.push('\n');
+        {
+            // anchors start over with each document
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.anchors(loader.anchors());
+            emitter.dump(&docs[0]).unwrap();
+        }
+        let reloaded = YamlLoader::load_from_str(&writer).unwrap();
+        assert_eq!(reloaded.len(), 2);
+        assert_eq!(reloaded[0], expanded[0]);
+        assert_eq!(reloaded[1], expanded[0]);
+        assert_eq!(writer.matches("&a1").count(), 2);
+        assert_eq!(writer.matches("&a2").count(), 2);
+
+        let mut key = Hash::new();
+        key.insert(Yaml::Alias(1), Yaml::Alias(1));
+        key.insert(Yaml::String("z".to_owned()), Yaml::Alias(9));
+        writer.clear();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.anchors(loader.anchors());
+            emitter.dump(&Yaml::Hash(key)).unwrap();
+        }
+        assert_eq!(writer, "---\n? &a1\n  - x\n  - y: 1\n: *a1\nz: ~");
+
+        writer.clear();
+        {
+            let mut emitter = YamlEmitter::new(&mut writer);
+            emitter.anchors(loader.anchors());
+            emitter.output_format(OutputFormat::JsonCompact);
+            emitter.dump(&docs[0]["d"]).unwrap();
+        }
+        assert_eq!(writer, r#"{"k":["x",{"y":1}]}"#);
+    }
 }
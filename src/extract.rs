@@ -0,0 +1,421 @@
+//! Typed extraction of values from `Yaml` documents, with errors that say where the
+//! offending node is, what was expected and what was found.
+//!
+//! A `Node` is a reference into a document together with its `Path` from the root.
+//! `get` and `index` step into mappings and sequences, and `to` converts a node
+//! into any type implementing `FromYaml`. Integer conversions are checked, so a port
+//! of `70000` read as a `u16` is reported rather than truncated.
+//!
+//! Looking up a missing key or index is not an error by itself: it gives a missing
+//! node, which converts into `None` for `Option<T>` and fails for anything else.
+//!
+//! # Examples
+//!
+//! ```
+//! use yaml_rust::YamlLoader;
+//! use yaml_rust::extract::Node;
+//!
+//! let docs = YamlLoader::load_from_str("
+//! servers:
+//! - {host: a, port: 80}
+//! - {host: b, port: 8080}
+//! - {host: c, port: 80a}
+//! ").unwrap();
+//! let servers = Node::new(&docs[0]).get("servers").unwrap();
+//! let port: u16 = servers.index(1).unwrap().get("port").unwrap().to().unwrap();
+//! assert_eq!(port, 8080);
+//!
+//! let err = servers.index(2).unwrap().get("port").unwrap().to::<u16>().unwrap_err();
+//! assert_eq!(err.to_string(), "expected integer at servers[2].port, found string \"80a\"");
+//!
+//! let user: Option<String> = servers.index(0).unwrap().get("user").unwrap().to().unwrap();
+//! assert_eq!(user, None);
+//! ```
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use query::{Path, PathSegment};
+use timestamp::Timestamp;
+use yaml::Yaml;
+
+/// A value that could not be extracted: the node at `path` is not of the expected
+/// type, is out of range for it, or is missing.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub struct ExtractError {
+    path: Path,
+    expected: String,
+    found: String,
+}
+
+impl ExtractError {
+    /// The path of the offending node.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// What was expected, e.g. `integer`, `mapping` or `u16`.
+    pub fn expected(&self) -> &str {
+        &self.expected
+    }
+
+    /// A description of the node that was found, e.g. `string "80a"`, or `nothing`
+    /// for a missing node.
+    pub fn found(&self) -> &str {
+        &self.found
+    }
+}
+
+impl Error for ExtractError {
+    fn description(&self) -> &str {
+        "unexpected value"
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_root() {
+            write!(formatter, "expected {}, found {}", self.expected, self.found)
+        } else {
+            write!(formatter, "expected {} at {}, found {}", self.expected, self.path, self.found)
+        }
+    }
+}
+
+// A short description of a node for error messages, including the value of scalars.
+fn describe(node: &Yaml) -> String {
+    match *node {
+        Yaml::Real(ref v) => format!("float {}", v),
+        Yaml::Integer(v) => format!("integer {}", v),
+        Yaml::BigInteger(ref v) => format!("integer {}", v),
+        Yaml::String(ref v) => format!("string {:?}", v),
+        Yaml::Boolean(v) => format!("boolean {}", v),
+        Yaml::Timestamp(ref v) => format!("timestamp {}", v),
+        Yaml::Binary(_) => "binary data".to_owned(),
+        Yaml::Array(_) => "sequence".to_owned(),
+        Yaml::Hash(_) => "mapping".to_owned(),
+        Yaml::Set(_) => "set".to_owned(),
+        Yaml::OrderedMap(_) => "ordered mapping".to_owned(),
+        Yaml::Pairs(_) => "pairs".to_owned(),
+        Yaml::Tagged(ref tag, ref v) => format!("{} {}", tag, describe(v)),
+        Yaml::Alias(_) => "alias".to_owned(),
+        Yaml::Null => "null".to_owned(),
+        Yaml::BadValue => "bad value".to_owned(),
+    }
+}
+
+/// A node of a document and its path from the root, or a missing node where a
+/// lookup found nothing.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Node<'a> {
+    yaml: Option<&'a Yaml>,
+    path: Path,
+}
+
+impl<'a> Node<'a> {
+    /// The root node of a document.
+    pub fn new(yaml: &'a Yaml) -> Node<'a> {
+        Node {
+            yaml: Some(yaml),
+            path: Path::new(),
+        }
+    }
+
+    /// The node, or `None` if it is missing.
+    pub fn yaml(&self) -> Option<&'a Yaml> {
+        self.yaml
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_missing(&self) -> bool {
+        self.yaml.is_none()
+    }
+
+    /// An error for this node, which was expected to be `expected`. Useful for
+    /// implementing `FromYaml`.
+    pub fn error(&self, expected: &str) -> ExtractError {
+        ExtractError {
+            path: self.path.clone(),
+            expected: expected.to_owned(),
+            found: match self.yaml {
+                Some(yaml) => describe(yaml),
+                None => "nothing".to_owned(),
+            },
+        }
+    }
+
+    // The node with any tag stripped, or `None` if it is missing.
+    fn value(&self) -> Option<&'a Yaml> {
+        self.yaml.map(Yaml::untagged)
+    }
+
+    fn child(&self, segment: PathSegment, yaml: Option<&'a Yaml>) -> Node<'a> {
+        Node {
+            yaml: yaml,
+            path: self.path.child(segment),
+        }
+    }
+
+    /// The value of `key` in this mapping. The result is a missing node if the key
+    /// is absent, or if this node is itself missing or null.
+    pub fn get(&self, key: &str) -> Result<Node<'a>, ExtractError> {
+        let key = Yaml::String(key.to_owned());
+        let yaml = match self.value() {
+            Some(&Yaml::Hash(ref h)) | Some(&Yaml::OrderedMap(ref h)) => h.get(&key),
+            None | Some(&Yaml::Null) => None,
+            Some(_) => return Err(self.error("mapping")),
+        };
+        Ok(self.child(PathSegment::Key(key), yaml))
+    }
+
+    /// The element `index` of this sequence. The result is a missing node if the
+    /// index is out of range, or if this node is itself missing or null.
+    pub fn index(&self, index: usize) -> Result<Node<'a>, ExtractError> {
+        let yaml = match self.value() {
+            Some(&Yaml::Array(ref v)) => v.get(index),
+            None | Some(&Yaml::Null) => None,
+            Some(_) => return Err(self.error("sequence")),
+        };
+        Ok(self.child(PathSegment::Index(index), yaml))
+    }
+
+    /// The elements of this sequence.
+    pub fn items(&self) -> Result<Vec<Node<'a>>, ExtractError> {
+        match self.value() {
+            Some(&Yaml::Array(ref v)) => {
+                Ok(v.iter()
+                    .enumerate()
+                    .map(|(i, item)| self.child(PathSegment::Index(i), Some(item)))
+                    .collect())
+            },
+            _ => Err(self.error("sequence")),
+        }
+    }
+
+    /// The keys and values of this mapping, in document order.
+    pub fn entries(&self) -> Result<Vec<(&'a Yaml, Node<'a>)>, ExtractError> {
+        match self.value() {
+            Some(&Yaml::Hash(ref h)) | Some(&Yaml::OrderedMap(ref h)) => {
+                Ok(h.iter()
+                    .map(|(k, v)| (k, self.child(PathSegment::Key(k.clone()), Some(v))))
+                    .collect())
+            },
+            _ => Err(self.error("mapping")),
+        }
+    }
+
+    /// Convert this node into a `T`.
+    pub fn to<T: FromYaml>(&self) -> Result<T, ExtractError> {
+        T::from_yaml(self)
+    }
+}
+
+/// Types that can be extracted from a `Node`.
+pub trait FromYaml: Sized {
+    fn from_yaml(node: &Node) -> Result<Self, ExtractError>;
+}
+
+impl FromYaml for Yaml {
+    fn from_yaml(node: &Node) -> Result<Yaml, ExtractError> {
+        match node.yaml() {
+            Some(yaml) => Ok(yaml.clone()),
+            None => Err(node.error("a value")),
+        }
+    }
+}
+
+impl FromYaml for String {
+    fn from_yaml(node: &Node) -> Result<String, ExtractError> {
+        match node.value() {
+            Some(&Yaml::String(ref v)) => Ok(v.clone()),
+            _ => Err(node.error("string")),
+        }
+    }
+}
+
+impl FromYaml for bool {
+    fn from_yaml(node: &Node) -> Result<bool, ExtractError> {
+        match node.value() {
+            Some(&Yaml::Boolean(v)) => Ok(v),
+            _ => Err(node.error("boolean")),
+        }
+    }
+}
+
+impl FromYaml for f64 {
+    /// Floats and integers both convert; integers are rounded to the nearest `f64`.
+    fn from_yaml(node: &Node) -> Result<f64, ExtractError> {
+        let value = match node.value() {
+            Some(yaml @ &Yaml::Real(_)) => yaml.as_f64(),
+            Some(&Yaml::Integer(v)) => Some(v as f64),
+            Some(&Yaml::BigInteger(ref v)) => v.parse().ok(),
+            _ => None,
+        };
+        value.ok_or_else(|| node.error("float"))
+    }
+}
+
+impl FromYaml for Timestamp {
+    fn from_yaml(node: &Node) -> Result<Timestamp, ExtractError> {
+        match node.value() {
+            Some(&Yaml::Timestamp(v)) => Ok(v),
+            _ => Err(node.error("timestamp")),
+        }
+    }
+}
+
+macro_rules! from_yaml_int (
+    ($($t:ident)*) => ($(
+impl FromYaml for $t {
+    /// Fails with the target type as the expectation if the integer is out of range.
+    fn from_yaml(node: &Node) -> Result<$t, ExtractError> {
+        let v = match node.value() {
+            Some(v @ &Yaml::Integer(_)) | Some(v @ &Yaml::BigInteger(_)) => v,
+            _ => return Err(node.error("integer")),
+        };
+        let n = match v.as_i128() {
+            Some(n) => $t::try_from(n).ok(),
+            None => v.as_u128().and_then(|n| $t::try_from(n).ok()),
+        };
+        n.ok_or_else(|| node.error(stringify!($t)))
+    }
+}
+    )*);
+);
+
+from_yaml_int!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize);
+
+impl<T: FromYaml> FromYaml for Option<T> {
+    /// A missing or null node gives `None`.
+    fn from_yaml(node: &Node) -> Result<Option<T>, ExtractError> {
+        match node.value() {
+            None | Some(&Yaml::Null) => Ok(None),
+            Some(_) => T::from_yaml(node).map(Some),
+        }
+    }
+}
+
+impl<T: FromYaml> FromYaml for Vec<T> {
+    fn from_yaml(node: &Node) -> Result<Vec<T>, ExtractError> {
+        let mut out = Vec::new();
+        for item in try!(node.items()) {
+            out.push(try!(item.to()));
+        }
+        Ok(out)
+    }
+}
+
+impl<T: FromYaml> FromYaml for BTreeMap<String, T> {
+    fn from_yaml(node: &Node) -> Result<BTreeMap<String, T>, ExtractError> {
+        let mut out = BTreeMap::new();
+        for (k, v) in try!(node.entries()) {
+            let key = match *k {
+                Yaml::String(ref k) => k.clone(),
+                _ => {
+                    return Err(ExtractError {
+                        path: v.path.clone(),
+                        expected: "string key".to_owned(),
+                        found: describe(k),
+                    })
+                },
+            };
+            out.insert(key, try!(v.to()));
+        }
+        Ok(out)
+    }
+}
+
+impl Yaml {
+    /// Convert this document into a `T`. See the `extract` module.
+    pub fn extract<T: FromYaml>(&self) -> Result<T, ExtractError> {
+        Node::new(self).to()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yaml::YamlLoader;
+
+    #[test]
+    fn test_extract() {
+        let docs = YamlLoader::load_from_str("
+name: demo
+replicas: 3
+ratio: 0.5
+debug: false
+ports: [80, 443]
+limits: {cpu: 2, memory: 512}
+created: 2001-12-14
+owner: ~
+").unwrap();
+        let doc = Node::new(&docs[0]);
+        assert_eq!(doc.get("name").unwrap().to::<String>().unwrap(), "demo");
+        assert_eq!(doc.get("replicas").unwrap().to::<u8>().unwrap(), 3);
+        let big = Yaml::from_str("340282366920938463463374607431768211455");
+        assert_eq!(Node::new(&big).to::<u128>().unwrap(), u128::max_value());
+        assert!(Node::new(&big).to::<i128>().is_err());
+        let neg = Yaml::from_str("-129");
+        assert_eq!(Node::new(&neg).to::<i16>().unwrap(), -129);
+        assert!(Node::new(&neg).to::<i8>().is_err());
+        assert!(Node::new(&neg).to::<usize>().is_err());
+        assert_eq!(doc.get("replicas").unwrap().to::<f64>().unwrap(), 3.0);
+        assert_eq!(doc.get("ratio").unwrap().to::<f64>().unwrap(), 0.5);
+        assert_eq!(doc.get("debug").unwrap().to::<bool>().unwrap(), false);
+        assert_eq!(doc.get("ports").unwrap().to::<Vec<u16>>().unwrap(), vec![80, 443]);
+        assert_eq!(doc.get("ports").unwrap().index(1).unwrap().to::<u32>().unwrap(), 443);
+        let limits = doc.get("limits").unwrap().to::<BTreeMap<String, i64>>().unwrap();
+        assert_eq!(limits["memory"], 512);
+        assert_eq!(doc.get("created").unwrap().to::<Timestamp>().unwrap().day, 14);
+        assert_eq!(doc.get("owner").unwrap().to::<Option<String>>().unwrap(), None);
+        assert_eq!(doc.get("missing").unwrap().to::<Option<String>>().unwrap(), None);
+        assert_eq!(doc.get("missing").unwrap().get("deeper").unwrap().to::<Option<i32>>().unwrap(), None);
+        assert_eq!(doc.get("ports").unwrap().index(5).unwrap().to::<Option<i32>>().unwrap(), None);
+        assert_eq!(docs[0]["ports"].extract::<Vec<i64>>().unwrap(), vec![80, 443]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let docs = YamlLoader::load_from_str("
+servers:
+- {host: a, port: 70000}
+- {host: b, port: -1}
+- {host: c, port: 80a, 'odd key': [x]}
+big: 123456789012345678901234567890
+").unwrap();
+        let doc = Node::new(&docs[0]);
+        let servers = doc.get("servers").unwrap();
+        let error = |node: &Node, expected: &str| node.to::<u16>().unwrap_err().to_string() == expected;
+        assert!(error(&servers.index(0).unwrap().get("port").unwrap(),
+                      "expected u16 at servers[0].port, found integer 70000"));
+        assert!(error(&servers.index(1).unwrap().get("port").unwrap(),
+                      "expected u16 at servers[1].port, found integer -1"));
+        assert!(error(&servers.index(2).unwrap().get("port").unwrap(),
+                      "expected integer at servers[2].port, found string \"80a\""));
+        assert!(error(&servers.index(3).unwrap().get("port").unwrap(),
+                      "expected integer at servers[3].port, found nothing"));
+        assert!(error(&doc.get("big").unwrap(),
+                      "expected u16 at big, found integer 123456789012345678901234567890"));
+        assert!(error(&doc, "expected integer, found mapping"));
+
+        let err = doc.get("big").unwrap().get("x").unwrap_err();
+        assert_eq!(err.to_string(),
+                   "expected mapping at big, found integer 123456789012345678901234567890");
+        assert_eq!(err.expected(), "mapping");
+        let err = servers.index(2).unwrap().get("odd key").unwrap().to::<Vec<i8>>().unwrap_err();
+        assert_eq!(err.to_string(), "expected integer at servers[2][\"odd key\"][0], found string \"x\"");
+        assert_eq!(err.path().segments().len(), 4);
+        let err = servers.to::<BTreeMap<String, Yaml>>().unwrap_err();
+        assert_eq!(err.to_string(), "expected mapping at servers, found sequence");
+        assert_eq!(servers.get("host").unwrap_err().found(), "sequence");
+        let err = Node::new(&YamlLoader::load_from_str("{1: a}").unwrap()[0])
+            .to::<BTreeMap<String, String>>().unwrap_err();
+        assert_eq!(err.to_string(), "expected string key at [1], found integer 1");
+        assert_eq!(doc.get("missing").unwrap().to::<Yaml>().unwrap_err().to_string(),
+                   "expected a value at missing, found nothing");
+    }
+}
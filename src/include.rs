@@ -0,0 +1,432 @@
+//! Composition of documents split across files with `!include`.
+//!
+//! An `IncludeLoader` loads a file and replaces each scalar tagged `!include` by
+//! the document of the file it names, loaded the same way. The file name is
+//! relative to the including file, and may be followed by `#` and a JSON Pointer
+//! to include only part of the document, e.g. `!include common.yaml#/resources`.
+//!
+//! Every file must lie inside the loader's root directory, after following `..`
+//! and symbolic links, and a file may not include itself, directly or through
+//! other files. A file included several times is read once per `load_file`, but
+//! each inclusion counts towards the loader's `max_includes`.
+//!
+//! Errors give the file and position where they occurred, followed by the chain
+//! of `!include` tags that led there.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use yaml_rust::include::IncludeLoader;
+//!
+//! let mut loader = IncludeLoader::new("config");
+//! loader.configure(|l| l.merge_keys(true));
+//! match loader.load_file("app.yaml") {
+//!     Ok(docs) => println!("{:?}", docs[0]["database"]),
+//!     // e.g. "variable DB_HOST is not set at line 2 column 7 of db.yaml
+//!     //       included from line 4 column 11 of app.yaml"
+//!     Err(e) => println!("{}", e),
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use pointer::Pointer;
+use scanner::{Marker, ScanError};
+use yaml::{Yaml, YamlLoader};
+
+/// An error in one of the files of an include tree.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub struct IncludeError {
+    file: PathBuf,
+    mark: Option<Marker>,
+    info: String,
+    chain: Vec<(PathBuf, Marker)>,
+}
+
+impl IncludeError {
+    /// The file the error occurred in, relative to the root directory if it is
+    /// inside it.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// The position of the error in `file`, unless the file itself could not be
+    /// read.
+    pub fn marker(&self) -> Option<&Marker> {
+        self.mark.as_ref()
+    }
+
+    /// The `!include` tags that led to `file`, innermost first, each with the file
+    /// it is in.
+    pub fn chain(&self) -> &[(PathBuf, Marker)] {
+        &self.chain
+    }
+}
+
+impl Error for IncludeError {
+    fn description(&self) -> &str {
+        &self.info
+    }
+}
+
+impl fmt::Display for IncludeError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        try!(formatter.write_str(&self.info));
+        if let Some(mark) = self.mark {
+            try!(write!(formatter, " at line {} column {}", mark.line(), mark.col() + 1));
+        }
+        try!(write!(formatter, " of {}", self.file.display()));
+        for &(ref file, mark) in &self.chain {
+            try!(write!(formatter, "\nincluded from line {} column {} of {}",
+                        mark.line(), mark.col() + 1, file.display()));
+        }
+        Ok(())
+    }
+}
+
+type Configure = Rc<Fn(&mut YamlLoader)>;
+
+/// Loads files whose `!include` tags are replaced by the documents they name.
+pub struct IncludeLoader {
+    root: PathBuf,
+    configure: Option<Configure>,
+    max_includes: usize,
+}
+
+// The files being loaded, outermost first, and where each one after the first was
+// included: the name of the including file and the position of the tag.
+struct State {
+    root: PathBuf,
+    configure: Option<Configure>,
+    files: Vec<PathBuf>,
+    sites: Vec<(PathBuf, Marker)>,
+    // the error of a nested file, kept while the loaders of its includers unwind
+    error: Option<IncludeError>,
+    // the documents of each file loaded so far, with the number of includes they
+    // expanded
+    cache: HashMap<PathBuf, (Vec<Yaml>, usize)>,
+    includes: usize,
+    max_includes: usize,
+}
+
+impl State {
+    // How `file` is shown in errors.
+    fn name(&self, file: &Path) -> PathBuf {
+        file.strip_prefix(&self.root).unwrap_or(file).to_path_buf()
+    }
+
+    // An error in the file being loaded.
+    fn fail(&self, mark: Option<Marker>, info: &str) -> IncludeError {
+        IncludeError {
+            file: self.files.last().map(|f| self.name(f)).unwrap_or_default(),
+            mark: mark,
+            info: info.to_owned(),
+            chain: self.sites.iter().rev().cloned().collect(),
+        }
+    }
+
+    // Account for `count` more includes, the first at `mark` in the current file.
+    fn count_includes(&mut self, count: usize, mark: Marker) -> Result<(), IncludeError> {
+        self.includes = self.includes.saturating_add(count);
+        if self.includes > self.max_includes {
+            return Err(self.fail(Some(mark), &format!("exceeds the limit of {} includes", self.max_includes)));
+        }
+        Ok(())
+    }
+
+    // The canonical path of `file`, checked to be inside the root and not already
+    // being loaded. `mark` is the position of the include in the current file.
+    fn resolve(&self, file: &Path, mark: Option<Marker>) -> Result<PathBuf, IncludeError> {
+        let fail = |info: String| match mark {
+            Some(_) => self.fail(mark, &info),
+            None => IncludeError { file: self.name(file), mark: None, info: info, chain: Vec::new() },
+        };
+        let outside = || fail(format!("{} is outside the root directory", file.display()));
+        // check before touching the file system, then again once symbolic links
+        // are followed
+        if !normalize(file).starts_with(&self.root) {
+            return Err(outside());
+        }
+        let path = try!(fs::canonicalize(file).map_err(|e| fail(format!("cannot read {}: {}", file.display(), e))));
+        if !path.starts_with(&self.root) {
+            return Err(outside());
+        }
+        if let Some(start) = self.files.iter().position(|f| *f == path) {
+            let cycle: Vec<String> = self.files[start..].iter().chain(Some(&path))
+                .map(|f| self.name(f).display().to_string())
+                .collect();
+            return Err(fail(format!("include cycle {}", cycle.join(" -> "))));
+        }
+        Ok(path)
+    }
+}
+
+// `path` with `.` and `..` components resolved without following symbolic links.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normal.pop();
+            },
+            c => normal.push(c.as_os_str()),
+        }
+    }
+    normal
+}
+
+fn read_file(path: &Path) -> Result<String, String> {
+    let mut source = String::new();
+    match File::open(path).and_then(|mut f| f.read_to_string(&mut source)) {
+        Ok(_) => Ok(source),
+        Err(e) => Err(format!("cannot read file: {}", e)),
+    }
+}
+
+// Load the documents of `path`, which `resolve` has checked.
+fn load_docs(state: &Rc<RefCell<State>>, path: PathBuf) -> Result<Vec<Yaml>, IncludeError> {
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let includes = state.borrow().includes;
+    state.borrow_mut().files.push(path.clone());
+    let result = match read_file(&path) {
+        Ok(source) => {
+            let mut loader = YamlLoader::new();
+            if let Some(ref configure) = state.borrow().configure {
+                configure(&mut loader);
+            }
+            let handler_state = state.clone();
+            loader.register_tag("!include", move |node, mark| {
+                let spec = match node.as_str() {
+                    Some(spec) => spec.to_owned(),
+                    None => return Err(ScanError::new(mark, "!include expects a file name")),
+                };
+                include(&handler_state, &dir, &spec, mark).map_err(|e| {
+                    handler_state.borrow_mut().error = Some(e);
+                    ScanError::new(mark, "include failed")
+                })
+            });
+            loader.load(&source).map_err(|e| {
+                let nested = state.borrow_mut().error.take();
+                nested.unwrap_or_else(|| state.borrow().fail(Some(*e.marker()), e.description()))
+            })
+        },
+        Err(info) => Err(state.borrow().fail(None, &info)),
+    };
+    let mut state = state.borrow_mut();
+    state.files.pop();
+    if let Ok(ref docs) = result {
+        let expanded = state.includes - includes;
+        state.cache.insert(path, (docs.clone(), expanded));
+    }
+    result
+}
+
+// The node that `!include spec` at `mark` in the current file, in `dir`, stands for.
+fn include(state: &Rc<RefCell<State>>, dir: &Path, spec: &str, mark: Marker) -> Result<Yaml, IncludeError> {
+    let (file, fragment) = match spec.find('#') {
+        Some(n) => (&spec[..n], Some(&spec[n + 1..])),
+        None => (spec, None),
+    };
+    let path = try!(state.borrow().resolve(&dir.join(file), Some(mark)));
+    let cached = state.borrow().cache.get(&path).cloned();
+    let mut docs = match cached {
+        Some((docs, expanded)) => {
+            try!(state.borrow_mut().count_includes(1 + expanded, mark));
+            docs
+        },
+        None => {
+            try!(state.borrow_mut().count_includes(1, mark));
+            let site = {
+                let state = state.borrow();
+                (state.name(state.files.last().unwrap()), mark)
+            };
+            state.borrow_mut().sites.push(site);
+            let docs = load_docs(state, path);
+            state.borrow_mut().sites.pop();
+            try!(docs)
+        },
+    };
+    let doc = match docs.len() {
+        0 => Yaml::Null,
+        1 => docs.remove(0),
+        n => return Err(state.borrow().fail(Some(mark), &format!("{} has {} documents", file, n))),
+    };
+    match fragment {
+        None => Ok(doc),
+        Some(pointer) => Pointer::parse(pointer).and_then(|p| p.get(&doc).cloned())
+            .map_err(|e| state.borrow().fail(Some(mark), &format!("{} in {}", e, file))),
+    }
+}
+
+impl IncludeLoader {
+    /// A loader confined to the directory `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> IncludeLoader {
+        IncludeLoader {
+            root: root.as_ref().to_path_buf(),
+            configure: None,
+            max_includes: 10_000,
+        }
+    }
+
+    /// Limit the number of times files may be included while loading a file,
+    /// counting again every include inside a file that is included more than
+    /// once. 10,000 by default.
+    ///
+    /// A few files that each include the next one many times would otherwise
+    /// expand to an enormous tree. Loading fails with an error pointing at the
+    /// first `!include` that exceeds the limit.
+    pub fn max_includes(&mut self, limit: usize) {
+        self.max_includes = limit;
+    }
+
+    /// Set up the `YamlLoader` of each file with `configure`, e.g. to enable merge
+    /// keys or variables. A `!include` handler it registers is replaced.
+    pub fn configure<F>(&mut self, configure: F)
+        where F: Fn(&mut YamlLoader) + 'static {
+        self.configure = Some(Rc::new(configure));
+    }
+
+    /// Load the documents of `file`, relative to the root directory.
+    pub fn load_file<P: AsRef<Path>>(&self, file: P) -> Result<Vec<Yaml>, IncludeError> {
+        let root = try!(fs::canonicalize(&self.root).map_err(|e| IncludeError {
+            file: self.root.clone(),
+            mark: None,
+            info: format!("cannot read the root directory: {}", e),
+            chain: Vec::new(),
+        }));
+        let state = Rc::new(RefCell::new(State {
+            root: root,
+            configure: self.configure.clone(),
+            files: Vec::new(),
+            sites: Vec::new(),
+            error: None,
+            cache: HashMap::new(),
+            includes: 0,
+            max_includes: self.max_includes,
+        }));
+        let path = try!(state.borrow().resolve(&state.borrow().root.join(file), None));
+        load_docs(&state, path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process;
+    use super::*;
+
+    // A fresh directory holding `files`, given as name and contents.
+    fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("yaml-rust-include-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        for &(file, contents) in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            File::create(path).unwrap().write_all(contents.as_bytes()).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_include() {
+        let root = tree("include", &[
+            ("app.yaml", "name: app\ndb: !include parts/db.yaml\nport: !include parts/db.yaml#/port\n"),
+            ("parts/db.yaml", "host: !include ../host.yaml\nport: 5432\n"),
+            ("host.yaml", "db.internal\n"),
+            ("empty.yaml", "# nothing\n"),
+            ("main.yaml", "base: &b {x: !include empty.yaml}\nc: {<<: *b, y: 2}\n"),
+        ]);
+        let docs = IncludeLoader::new(&root).load_file("app.yaml").unwrap();
+        assert_eq!(docs[0]["db"]["host"].as_str(), Some("db.internal"));
+        assert_eq!(docs[0]["port"].as_i64(), Some(5432));
+
+        let mut loader = IncludeLoader::new(&root);
+        loader.configure(|l| {
+            l.merge_keys(true);
+            l.register_tag("!include", |_, _| Ok(Yaml::Integer(0)));
+        });
+        let docs = loader.load_file("main.yaml").unwrap();
+        assert!(docs[0]["c"]["x"].is_null());
+        assert_eq!(docs[0]["c"]["y"].as_i64(), Some(2));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        let root = tree("errors", &[
+            ("a.yaml", "x: 1\nb: !include sub/b.yaml\n"),
+            ("sub/b.yaml", "c: !include ../c.yaml\n"),
+            ("c.yaml", "a: 1\nb: c: d\n"),
+            ("cycle.yaml", "self: !include sub/../cycle2.yaml\n"),
+            ("cycle2.yaml", "back: !include cycle.yaml\n"),
+            ("escape.yaml", "x: !include ../outside.yaml\n"),
+            ("missing.yaml", "x: !include nope.yaml\n"),
+            ("fragment.yaml", "x: !include c2.yaml#/b\n"),
+            ("c2.yaml", "a: 1\n"),
+            ("multi.yaml", "x: !include two.yaml\n"),
+            ("two.yaml", "--- 1\n--- 2\n"),
+            ("nested.yaml", "x: !include [a.yaml]\n"),
+        ]);
+        let loader = IncludeLoader::new(&root);
+        let err = loader.load_file("a.yaml").unwrap_err();
+        assert_eq!(err.file(), Path::new("c.yaml"));
+        assert_eq!(err.chain(), &[
+            (PathBuf::from("sub/b.yaml"), Marker::new(12, 1, 12)),
+            (PathBuf::from("a.yaml"), Marker::new(17, 2, 12)),
+        ][..]);
+        assert_eq!(err.to_string(), "\
+mapping values are not allowed in this context at line 2 column 5 of c.yaml
+included from line 1 column 13 of sub/b.yaml
+included from line 2 column 13 of a.yaml");
+
+        let message = |file: &str| {
+            let err = loader.load_file(file).unwrap_err();
+            err.to_string().lines().next().unwrap().to_owned()
+        };
+        assert_eq!(message("cycle.yaml"),
+                   "include cycle cycle.yaml -> cycle2.yaml -> cycle.yaml at line 1 column 16 of cycle2.yaml");
+        assert_eq!(message("escape.yaml"), format!("{} is outside the root directory at line 1 column 13 of escape.yaml",
+                                                   root.join("../outside.yaml").display()));
+        assert!(message("missing.yaml").starts_with(&format!("cannot read {}: ", root.join("nope.yaml").display())));
+        assert_eq!(message("fragment.yaml"), "missing key at /b in c2.yaml at line 1 column 13 of fragment.yaml");
+        assert_eq!(message("multi.yaml"), "two.yaml has 2 documents at line 1 column 13 of multi.yaml");
+        assert_eq!(message("nested.yaml"), "!include expects a file name at line 1 column 13 of nested.yaml");
+        let err = loader.load_file("../x.yaml").unwrap_err();
+        assert!(err.marker().is_none() && err.chain().is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_fan_out() {
+        let mut files = vec![("l9.yaml".to_owned(), "leaf".to_owned())];
+        for level in 0..9 {
+            let refs = vec![format!("!include l{}.yaml", level + 1); 10].join(", ");
+            files.push((format!("l{}.yaml", level), format!("[{}]", refs)));
+        }
+        let files: Vec<(&str, &str)> = files.iter().map(|&(ref f, ref c)| (f.as_str(), c.as_str())).collect();
+        let root = tree("fan-out", &files);
+        let mut loader = IncludeLoader::new(&root);
+        let docs = loader.load_file("l6.yaml").unwrap();
+        assert_eq!(docs[0][9][9][9].as_str(), Some("leaf"));
+
+        // l5 includes l6 ten times, which expand to 1110 includes each
+        let err = loader.load_file("l5.yaml").unwrap_err();
+        assert_eq!(err.to_string(), "exceeds the limit of 10000 includes at line 1 column 173 of l5.yaml");
+        loader.max_includes(100);
+        let err = loader.load_file("l6.yaml").unwrap_err();
+        assert_eq!(err.to_string(), "\
+exceeds the limit of 100 includes at line 1 column 173 of l7.yaml
+included from line 1 column 11 of l6.yaml");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -40,16 +40,37 @@
 #![cfg_attr(feature = "cargo-clippy", allow(match_same_arms))]
 
 extern crate linked_hash_map;
+#[cfg(feature = "num-bigint")]
+extern crate num_bigint;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
 
 pub mod yaml;
 pub mod scanner;
 pub mod parser;
 pub mod emitter;
+pub mod timestamp;
+pub mod query;
+pub mod pointer;
+pub mod extract;
+pub mod diff;
+pub mod patch;
+pub mod merge;
+pub mod include;
+#[cfg(feature = "serde")]
+pub mod de;
+#[cfg(feature = "serde")]
+pub mod ser;
+mod base64;
 
 // reexport key APIs
 pub use scanner::ScanError;
 pub use parser::Event;
-pub use yaml::{Yaml, YamlLoader};
+pub use yaml::{Yaml, YamlLoader, MarkedYaml, DuplicateKeys, IntegerError, Schema, Tag};
 pub use emitter::{YamlEmitter, EmitError};
 
 #[cfg(test)]
@@ -0,0 +1,337 @@
+//! Deep merging of layered configuration documents, e.g. a base file overlaid by
+//! environment-specific and local ones.
+//!
+//! Mappings are merged key by key, recursively: a key present in the overlay
+//! replaces or merges into the base's value, and other base keys are kept. How
+//! sequences and `null` overlay values combine is set on a `Merger`. Any other pair
+//! of nodes is resolved by taking the overlay's.
+//!
+//! `Merger::merge_layers` also records provenance: which layer supplied each node
+//! of the result.
+//!
+//! # Examples
+//!
+//! ```
+//! use yaml_rust::YamlLoader;
+//! use yaml_rust::merge::{ArrayMerge, Merger, NullMerge};
+//! use yaml_rust::query::{Path, PathSegment};
+//! use yaml_rust::Yaml;
+//!
+//! let base = YamlLoader::load_from_str("
+//! replicas: 1
+//! debug: false
+//! containers: [{name: web, image: 'nginx:1.0'}, {name: log, image: fluentd}]
+//! ").unwrap().remove(0);
+//! let prod = YamlLoader::load_from_str("
+//! replicas: 3
+//! debug: null
+//! containers: [{name: web, image: 'nginx:1.2'}]
+//! ").unwrap().remove(0);
+//!
+//! let mut merger = Merger::new();
+//! merger.arrays(ArrayMerge::ByKey("name".to_owned()));
+//! merger.nulls(NullMerge::Remove);
+//! let merged = merger.merge_layers(&[("base.yaml", &base), ("prod.yaml", &prod)]);
+//! assert_eq!(merged.value(), &YamlLoader::load_from_str("
+//! replicas: 3
+//! containers: [{name: web, image: 'nginx:1.2'}, {name: log, image: fluentd}]
+//! ").unwrap()[0]);
+//!
+//! let key = |k: &str| PathSegment::Key(Yaml::String(k.to_owned()));
+//! let containers = Path::new().child(key("containers"));
+//! assert_eq!(merged.source(&containers.child(PathSegment::Index(0)).child(key("image"))), Some("prod.yaml"));
+//! assert_eq!(merged.source(&containers.child(PathSegment::Index(1))), Some("base.yaml"));
+//! ```
+
+use std::collections::BTreeMap;
+use query::{Path, PathSegment};
+use yaml::Yaml;
+
+/// How a sequence in an overlay combines with a sequence in the base.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub enum ArrayMerge {
+    /// The overlay's sequence replaces the base's (the default).
+    Replace,
+    /// The overlay's elements are appended to the base's.
+    Append,
+    /// Mapping elements with the same value for this key are merged, and other
+    /// overlay elements are appended, e.g. containers matched by `name`.
+    ByKey(String),
+}
+
+/// How a `null` value in an overlay mapping combines with the base.
+#[derive(Clone, Copy, PartialEq, Debug, Eq)]
+pub enum NullMerge {
+    /// The null replaces the base's value (the default).
+    Replace,
+    /// The key is removed from the result.
+    Remove,
+    /// The base's value is kept, and the key is not added if the base lacks it.
+    /// A null overlay document leaves the whole base unchanged.
+    Ignore,
+}
+
+/// The result of merging layers, with the layer each node came from.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Merged {
+    value: Yaml,
+    layers: Vec<String>,
+    sources: BTreeMap<Path, usize>,
+}
+
+impl Merged {
+    pub fn value(&self) -> &Yaml {
+        &self.value
+    }
+
+    pub fn into_value(self) -> Yaml {
+        self.value
+    }
+
+    /// The name of the layer that supplied the node at `path`. Nodes merged from
+    /// several layers, such as a mapping extended by an overlay, have no single
+    /// source and give `None`, as do paths outside the result.
+    pub fn source(&self, path: &Path) -> Option<&str> {
+        if path.get(&self.value).is_none() {
+            return None;
+        }
+        let mut path = path.clone();
+        loop {
+            if let Some(&layer) = self.sources.get(&path) {
+                return Some(&self.layers[layer]);
+            }
+            if path.pop().is_none() {
+                return None;
+            }
+        }
+    }
+
+    /// The nodes each taken whole from one layer, with that layer's name, in path
+    /// order. Every node of the result is one of these, a descendant of one, or a
+    /// merged collection.
+    pub fn sources(&self) -> Vec<(&Path, &str)> {
+        self.sources.iter().map(|(p, &l)| (p, &self.layers[l][..])).collect()
+    }
+}
+
+/// Merges overlay documents into a base.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub struct Merger {
+    arrays: ArrayMerge,
+    nulls: NullMerge,
+}
+
+// Record that the node at `path` comes whole from `layer`.
+fn set_source(path: &Path, layer: usize, sources: &mut BTreeMap<Path, usize>) {
+    remove_sources(path, sources);
+    sources.insert(path.clone(), layer);
+}
+
+// Forget the sources of the node at `path` and its descendants.
+fn remove_sources(path: &Path, sources: &mut BTreeMap<Path, usize>) {
+    let below: Vec<Path> = sources.range(path.clone()..)
+        .take_while(|&(p, _)| p.segments().starts_with(path.segments()))
+        .map(|(p, _)| p.clone())
+        .collect();
+    for p in below {
+        sources.remove(&p);
+    }
+}
+
+// Before merging into the node at `path`, which came whole from one layer, give
+// each of its children that layer instead.
+fn split_source(path: &Path, node: &Yaml, sources: &mut BTreeMap<Path, usize>) {
+    let layer = match sources.remove(path) {
+        Some(layer) => layer,
+        None => return,
+    };
+    match *node {
+        Yaml::Hash(ref h) => for k in h.keys() {
+            sources.insert(path.child(PathSegment::Key(k.clone())), layer);
+        },
+        Yaml::Array(ref v) => for i in 0..v.len() {
+            sources.insert(path.child(PathSegment::Index(i)), layer);
+        },
+        _ => {},
+    }
+}
+
+impl Merger {
+    pub fn new() -> Merger {
+        Merger {
+            arrays: ArrayMerge::Replace,
+            nulls: NullMerge::Replace,
+        }
+    }
+
+    pub fn arrays(&mut self, strategy: ArrayMerge) {
+        self.arrays = strategy;
+    }
+
+    pub fn nulls(&mut self, strategy: NullMerge) {
+        self.nulls = strategy;
+    }
+
+    /// Merge `overlay` into `base`.
+    pub fn merge(&self, base: &mut Yaml, overlay: &Yaml) {
+        self.merge_node(&mut Path::new(), base, overlay, 0, &mut BTreeMap::new());
+    }
+
+    /// Merge named layers in order, each overlaying the result of the previous ones.
+    pub fn merge_layers(&self, layers: &[(&str, &Yaml)]) -> Merged {
+        let mut merged = Merged {
+            value: Yaml::Null,
+            layers: layers.iter().map(|&(name, _)| name.to_owned()).collect(),
+            sources: BTreeMap::new(),
+        };
+        for (i, &(_, layer)) in layers.iter().enumerate() {
+            if i == 0 {
+                merged.value = layer.clone();
+                merged.sources.insert(Path::new(), 0);
+            } else {
+                self.merge_node(&mut Path::new(), &mut merged.value, layer, i, &mut merged.sources);
+            }
+        }
+        merged
+    }
+
+    fn merge_node(&self, path: &mut Path, base: &mut Yaml, overlay: &Yaml, layer: usize,
+                  sources: &mut BTreeMap<Path, usize>) {
+        if overlay.is_null() && self.nulls == NullMerge::Ignore {
+            return;
+        }
+        split_source(path, base, sources);
+        match (&mut *base, overlay) {
+            (&mut Yaml::Hash(ref mut b), &Yaml::Hash(ref o)) => {
+                for (k, v) in o {
+                    path.push(PathSegment::Key(k.clone()));
+                    if v.is_null() && self.nulls == NullMerge::Remove {
+                        b.remove(k);
+                        remove_sources(path, sources);
+                    } else if let Some(target) = b.get_mut(k) {
+                        self.merge_node(path, target, v, layer, sources);
+                    } else if !v.is_null() || self.nulls != NullMerge::Ignore {
+                        b.insert(k.clone(), v.clone());
+                        set_source(path, layer, sources);
+                    }
+                    path.pop();
+                }
+                return;
+            },
+            (&mut Yaml::Array(ref mut b), &Yaml::Array(ref o)) if self.arrays != ArrayMerge::Replace => {
+                for v in o {
+                    let matching = match self.arrays {
+                        ArrayMerge::ByKey(ref field) => {
+                            let field = Yaml::String(field.clone());
+                            match v.as_hash().and_then(|h| h.get(&field)) {
+                                Some(id) => b.iter().position(|e| {
+                                    e.as_hash().and_then(|h| h.get(&field)) == Some(id)
+                                }),
+                                None => None,
+                            }
+                        },
+                        _ => None,
+                    };
+                    match matching {
+                        Some(i) => {
+                            path.push(PathSegment::Index(i));
+                            self.merge_node(path, &mut b[i], v, layer, sources);
+                            path.pop();
+                        },
+                        None => {
+                            b.push(v.clone());
+                            set_source(&path.child(PathSegment::Index(b.len() - 1)), layer, sources);
+                        },
+                    }
+                }
+                return;
+            },
+            _ => {},
+        }
+        *base = overlay.clone();
+        set_source(path, layer, sources);
+    }
+}
+
+impl Yaml {
+    /// Deep-merge `overlay` into this document, replacing sequences and letting
+    /// nulls replace values. See `Merger` for other strategies.
+    pub fn merge(&mut self, overlay: &Yaml) {
+        Merger::new().merge(self, overlay);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yaml::YamlLoader;
+
+    fn load(s: &str) -> Yaml {
+        YamlLoader::load_from_str(s).unwrap().remove(0)
+    }
+
+    fn merged(merger: &Merger, base: &str, overlay: &str) -> Yaml {
+        let mut base = load(base);
+        merger.merge(&mut base, &load(overlay));
+        base
+    }
+
+    #[test]
+    fn test_strategies() {
+        let base = "{a: 1, b: {c: [1, 2], d: x}, e: [{id: 1, v: a}, {id: 2, v: b}]}";
+        let overlay = "{a: null, b: {c: [3], f: y}, e: [{id: 2, v: c}, {v: d}], g: null}";
+
+        let mut merger = Merger::new();
+        assert_eq!(merged(&merger, base, overlay),
+                   load("{a: null, b: {c: [3], d: x, f: y}, e: [{id: 2, v: c}, {v: d}], g: null}"));
+
+        merger.arrays(ArrayMerge::Append);
+        merger.nulls(NullMerge::Remove);
+        assert_eq!(merged(&merger, base, overlay),
+                   load("{b: {c: [1, 2, 3], d: x, f: y}, e: [{id: 1, v: a}, {id: 2, v: b}, {id: 2, v: c}, {v: d}]}"));
+
+        merger.arrays(ArrayMerge::ByKey("id".to_owned()));
+        merger.nulls(NullMerge::Ignore);
+        assert_eq!(merged(&merger, base, overlay),
+                   load("{a: 1, b: {c: [1, 2, 3], d: x, f: y}, e: [{id: 1, v: a}, {id: 2, v: c}, {v: d}]}"));
+        assert_eq!(merged(&merger, base, "~"), load(base));
+
+        let mut doc = load("{a: {b: 1}, c: [1]}");
+        doc.merge(&load("{a: {d: 2}, c: [2]}"));
+        assert_eq!(doc, load("{a: {b: 1, d: 2}, c: [2]}"));
+    }
+
+    #[test]
+    fn test_provenance() {
+        let base = load("{a: 1, b: {c: 2, d: [1]}, e: {f: 3}}");
+        let env = load("{b: {c: 4, d: [2]}, g: 5}");
+        let local = load("{b: {c: 6}, e: 7}");
+        let mut merger = Merger::new();
+        merger.arrays(ArrayMerge::Append);
+        let merged = merger.merge_layers(&[("base", &base), ("env", &env), ("local", &local)]);
+        assert_eq!(merged.value(), &load("{a: 1, b: {c: 6, d: [1, 2]}, e: 7, g: 5}"));
+
+        let path = |keys: &[&str]| {
+            let mut path = Path::new();
+            for k in keys {
+                match k.parse::<usize>() {
+                    Ok(i) => path.push(PathSegment::Index(i)),
+                    Err(_) => path.push(PathSegment::Key(Yaml::String((*k).to_owned()))),
+                }
+            }
+            path
+        };
+        assert_eq!(merged.source(&path(&["a"])), Some("base"));
+        assert_eq!(merged.source(&path(&["b", "c"])), Some("local"));
+        assert_eq!(merged.source(&path(&["b", "d", "0"])), Some("base"));
+        assert_eq!(merged.source(&path(&["b", "d", "1"])), Some("env"));
+        assert_eq!(merged.source(&path(&["e"])), Some("local"));
+        assert_eq!(merged.source(&path(&["g"])), Some("env"));
+        assert_eq!(merged.source(&path(&["b"])), None);
+        assert_eq!(merged.source(&path(&["x"])), None);
+        assert_eq!(merged.sources().len(), 6);
+
+        let single = Merger::new().merge_layers(&[("only", &base)]);
+        assert_eq!(single.source(&path(&["e", "f"])), Some("only"));
+    }
+}
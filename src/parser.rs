@@ -29,8 +29,31 @@ enum State {
     End
 }
 
+// Where the next token appears in a strict JSON document.
+#[derive(Clone, Copy, PartialEq, Debug, Eq)]
+enum JsonPos {
+    // before the top-level value
+    Start,
+    // after `[` or `{`
+    Open,
+    // after `,`
+    Comma,
+    // after the key token of an object member, which starts at this index
+    Key(usize),
+    // after the string key of an object member
+    KeyString,
+    // after `:`
+    Colon,
+    // after a complete value
+    Value,
+}
+
 /// `Event` is used with the low-level event base parsing API,
 /// see `EventReceiver` trait.
+///
+/// Since 0.5, `SequenceStart` and `MappingStart` carry the tag of the collection,
+/// like `Scalar` does; receivers written for 0.4 need an extra field in their
+/// patterns.
 #[derive(Clone, PartialEq, Debug, Eq)]
 pub enum Event {
     /// Reserved for internal use
@@ -43,11 +66,11 @@ pub enum Event {
     Alias(usize),
     /// Value, style, anchor_id, tag
     Scalar(String, TScalarStyle, usize, Option<TokenType>),
-    /// Anchor ID
-    SequenceStart(usize),
+    /// Anchor ID, tag
+    SequenceStart(usize, Option<TokenType>),
     SequenceEnd,
-    /// Anchor ID
-    MappingStart(usize),
+    /// Anchor ID, tag
+    MappingStart(usize, Option<TokenType>),
     MappingEnd
 }
 
@@ -70,8 +93,17 @@ pub struct Parser<T> {
     marks: Vec<Marker>,
     token: Option<Token>,
     current: Option<(Event, Marker)>,
+    // end of the source text of the event parsed last
+    end_mark: Marker,
+    scalar_end: Option<Marker>,
     anchors: HashMap<String, usize>,
     anchor_id: usize,
+    depth: usize,
+    max_depth: Option<usize>,
+    json: bool,
+    json_pos: JsonPos,
+    // whether each open flow collection of a JSON document is an object
+    json_flows: Vec<bool>,
 }
 
 
@@ -82,6 +114,12 @@ pub trait EventReceiver {
 
 pub trait MarkedEventReceiver {
     fn on_event(&mut self, ev: Event, _mark: Marker);
+
+    /// Like `on_event`, but also receives the marker just past the end of the
+    /// event's source text. Only scalars have an end distinct from `mark`.
+    fn on_spanned_event(&mut self, ev: Event, mark: Marker, _end: Marker) {
+        self.on_event(ev, mark)
+    }
 }
 
 impl<R: EventReceiver> MarkedEventReceiver for R {
@@ -101,13 +139,39 @@ impl<T: Iterator<Item=char>> Parser<T> {
             marks: Vec::new(),
             token: None,
             current: None,
+            end_mark: Marker::new(0, 1, 0),
+            scalar_end: None,
 
             anchors: HashMap::new(),
             // valid anchor_id starts from 1
             anchor_id: 1,
+            depth: 0,
+            max_depth: None,
+            json: false,
+            json_pos: JsonPos::Start,
+            json_flows: Vec::new(),
         }
     }
 
+    /// Limit how deeply sequences and mappings may be nested. Unlimited by default.
+    ///
+    /// Loading recurses once per nesting level, so untrusted input should be
+    /// parsed with a limit to turn a stack overflow into a `ScanError` pointing
+    /// at the collection that is nested too deeply.
+    pub fn max_depth(&mut self, limit: usize) {
+        self.max_depth = Some(limit);
+    }
+
+    /// Accept only JSON text: a single value written with flow collections,
+    /// double-quoted strings and keys, and plain scalars limited to `null`,
+    /// `true`, `false` and numbers. Comments, anchors, aliases, tags, block
+    /// collections, document markers, trailing commas and other YAML constructs
+    /// fail with a `ScanError` at the offending token.
+    pub fn strict_json(&mut self, strict: bool) {
+        self.json = strict;
+        self.scanner.strict_json(strict);
+    }
+
     pub fn peek(&mut self) -> Result<&(Event, Marker), ScanError> {
         match self.current {
             Some(ref x) => Ok(x),
@@ -145,7 +209,85 @@ impl<T: Iterator<Item=char>> Parser<T> {
                     None => Err(ScanError::new(self.scanner.mark(), "unexpected eof")),
                     Some(e) => Err(e),
                 },
-            Some(tok) => Ok(tok)
+            Some(tok) => {
+                if self.json {
+                    try!(self.check_json(&tok));
+                }
+                Ok(tok)
+            }
+        }
+    }
+
+    // Check that `tok` may appear at this point of a JSON document.
+    fn check_json(&mut self, tok: &Token) -> Result<(), ScanError> {
+        let Token(mark, ref t) = *tok;
+        let in_object = self.json_flows.last() == Some(&true);
+        let value_allowed = match self.json_pos {
+            JsonPos::Start | JsonPos::Colon => true,
+            JsonPos::Open | JsonPos::Comma => !in_object,
+            _ => false,
+        };
+        let pos = match *t {
+            TokenType::StreamStart(_) | TokenType::NoToken => return Ok(()),
+            TokenType::StreamEnd if self.json_pos == JsonPos::Value => return Ok(()),
+            TokenType::VersionDirective(..) | TokenType::TagDirective(..)
+                | TokenType::DocumentStart | TokenType::DocumentEnd => {
+                Err("directives and document markers are not allowed in JSON")
+            },
+            TokenType::BlockSequenceStart | TokenType::BlockMappingStart
+                | TokenType::BlockEntry | TokenType::BlockEnd => {
+                Err("block collections are not allowed in JSON")
+            },
+            TokenType::Anchor(_) | TokenType::Alias(_) => Err("anchors and aliases are not allowed in JSON"),
+            TokenType::Tag(..) => Err("tags are not allowed in JSON"),
+            TokenType::Key if in_object && (self.json_pos == JsonPos::Open || self.json_pos == JsonPos::Comma) => {
+                Ok(JsonPos::Key(mark.index()))
+            },
+            TokenType::Key if value_allowed => Err("single-pair mappings are not allowed in JSON"),
+            TokenType::Scalar(style, _) if self.json_pos == JsonPos::Key(mark.index()) => {
+                if style == TScalarStyle::DoubleQuoted {
+                    Ok(JsonPos::KeyString)
+                } else {
+                    Err("object keys must be double-quoted strings in JSON")
+                }
+            },
+            TokenType::Scalar(style, ref v) if value_allowed => match style {
+                TScalarStyle::DoubleQuoted => Ok(JsonPos::Value),
+                TScalarStyle::Plain if v == "null" || v == "true" || v == "false" || is_json_number(v) => {
+                    Ok(JsonPos::Value)
+                },
+                TScalarStyle::Plain => Err("plain scalars other than null, true, false and numbers are not allowed in JSON"),
+                _ => Err("strings must be double-quoted in JSON"),
+            },
+            TokenType::FlowSequenceStart | TokenType::FlowMappingStart if value_allowed => {
+                self.json_flows.push(*t == TokenType::FlowMappingStart);
+                Ok(JsonPos::Open)
+            },
+            TokenType::Value if self.json_pos == JsonPos::KeyString => Ok(JsonPos::Colon),
+            TokenType::FlowEntry if self.json_pos == JsonPos::Value && !self.json_flows.is_empty() => {
+                Ok(JsonPos::Comma)
+            },
+            TokenType::FlowSequenceEnd | TokenType::FlowMappingEnd
+                if self.json_pos == JsonPos::Value || self.json_pos == JsonPos::Open => {
+                self.json_flows.pop();
+                Ok(JsonPos::Value)
+            },
+            TokenType::FlowSequenceEnd | TokenType::FlowMappingEnd if self.json_pos == JsonPos::Comma => {
+                Err("trailing commas are not allowed in JSON")
+            },
+            _ => Err(match self.json_pos {
+                JsonPos::Open | JsonPos::Comma | JsonPos::Key(_) if in_object => "expected a double-quoted key",
+                JsonPos::KeyString => "expected ':' after an object key",
+                JsonPos::Value => "expected ',' or the end of a JSON array or object",
+                _ => "expected a JSON value",
+            }),
+        };
+        match pos {
+            Ok(pos) => {
+                self.json_pos = pos;
+                Ok(())
+            },
+            Err(info) => Err(ScanError::new(mark, info)),
         }
     }
 
@@ -169,28 +311,45 @@ impl<T: Iterator<Item=char>> Parser<T> {
         if self.state == State::End {
             return Ok((Event::StreamEnd, self.scanner.mark()));
         }
+        self.scalar_end = None;
         let (ev, mark) = try!(self.state_machine());
         // println!("EV {:?}", ev);
+        self.end_mark = self.scalar_end.take().unwrap_or(mark);
+        match ev {
+            Event::SequenceStart(..) | Event::MappingStart(..) => {
+                self.depth += 1;
+                if let Some(limit) = self.max_depth {
+                    if self.depth > limit {
+                        return Err(ScanError::new(mark, "exceeded the maximum nesting depth"));
+                    }
+                }
+            },
+            Event::SequenceEnd | Event::MappingEnd => self.depth -= 1,
+            _ => {}
+        }
         Ok((ev, mark))
     }
 
     pub fn load<R: MarkedEventReceiver>(&mut self, recv: &mut R, multi: bool)
         -> Result<(), ScanError> {
+        // receivers get the end of each scalar
+        self.scanner.track_scalar_ends(true);
         if !self.scanner.stream_started() {
             let (ev, mark) = try!(self.next());
             assert_eq!(ev, Event::StreamStart);
-            recv.on_event(ev, mark);
+            recv.on_spanned_event(ev, mark, self.end_mark);
         }
 
         if self.scanner.stream_ended() {
             // XXX has parsed?
-            recv.on_event(Event::StreamEnd, self.scanner.mark());
+            let mark = self.scanner.mark();
+            recv.on_spanned_event(Event::StreamEnd, mark, mark);
             return Ok(());
         }
         loop {
             let (ev, mark) = try!(self.next());
             if ev == Event::StreamEnd {
-                recv.on_event(ev, mark);
+                recv.on_spanned_event(ev, mark, self.end_mark);
                 return Ok(());
             }
             // clear anchors before a new document
@@ -206,7 +365,7 @@ impl<T: Iterator<Item=char>> Parser<T> {
     fn load_document<R: MarkedEventReceiver>(&mut self, first_ev: Event, mark: Marker, recv: &mut R)
         -> Result<(), ScanError> {
         assert_eq!(first_ev, Event::DocumentStart);
-        recv.on_event(first_ev, mark);
+        recv.on_spanned_event(first_ev, mark, self.end_mark);
 
         let (ev, mark) = try!(self.next());
         try!(self.load_node(ev, mark, recv));
@@ -214,7 +373,7 @@ impl<T: Iterator<Item=char>> Parser<T> {
         // DOCUMENT-END is expected.
         let (ev, mark) = try!(self.next());
         assert_eq!(ev, Event::DocumentEnd);
-        recv.on_event(ev, mark);
+        recv.on_spanned_event(ev, mark, self.end_mark);
 
         Ok(())
     }
@@ -223,15 +382,15 @@ impl<T: Iterator<Item=char>> Parser<T> {
         -> Result<(), ScanError> {
         match first_ev {
             Event::Alias(..) | Event::Scalar(..) => {
-                recv.on_event(first_ev, mark);
+                recv.on_spanned_event(first_ev, mark, self.end_mark);
                 Ok(())
             },
-            Event::SequenceStart(_) => {
-                recv.on_event(first_ev, mark);
+            Event::SequenceStart(..) => {
+                recv.on_spanned_event(first_ev, mark, self.end_mark);
                 self.load_sequence(recv)
             },
-            Event::MappingStart(_) => {
-                recv.on_event(first_ev, mark);
+            Event::MappingStart(..) => {
+                recv.on_spanned_event(first_ev, mark, self.end_mark);
                 self.load_mapping(recv)
             },
             _ => { println!("UNREACHABLE EVENT: {:?}", first_ev);
@@ -256,7 +415,7 @@ impl<T: Iterator<Item=char>> Parser<T> {
             key_mark = mark;
 
         }
-        recv.on_event(key_ev, key_mark);
+        recv.on_spanned_event(key_ev, key_mark, self.end_mark);
         Ok(())
     }
 
@@ -271,7 +430,7 @@ impl<T: Iterator<Item=char>> Parser<T> {
             ev = next_ev;
             mark = next_mark;
         }
-        recv.on_event(ev, mark);
+        recv.on_spanned_event(ev, mark, self.end_mark);
         Ok(())
     }
 
@@ -487,11 +646,12 @@ impl<T: Iterator<Item=char>> Parser<T> {
         match *try!(self.peek_token()) {
             Token(mark, TokenType::BlockEntry) if indentless_sequence => {
                 self.state = State::IndentlessSequenceEntry;
-                Ok((Event::SequenceStart(anchor_id), mark))
+                Ok((Event::SequenceStart(anchor_id, tag), mark))
             },
             Token(_, TokenType::Scalar(..)) => {
                 self.pop_state();
                 if let Token(mark, TokenType::Scalar(style, v)) = self.fetch_token() {
+                    self.scalar_end = self.scanner.scalar_end(mark);
                     Ok((Event::Scalar(v, style, anchor_id, tag), mark))
                 } else {
                     unreachable!()
@@ -499,19 +659,19 @@ impl<T: Iterator<Item=char>> Parser<T> {
             },
             Token(mark, TokenType::FlowSequenceStart) => {
                 self.state = State::FlowSequenceFirstEntry;
-                Ok((Event::SequenceStart(anchor_id), mark))
+                Ok((Event::SequenceStart(anchor_id, tag), mark))
             },
             Token(mark, TokenType::FlowMappingStart) => {
                 self.state = State::FlowMappingFirstKey;
-                Ok((Event::MappingStart(anchor_id), mark))
+                Ok((Event::MappingStart(anchor_id, tag), mark))
             },
             Token(mark, TokenType::BlockSequenceStart) if block => {
                 self.state = State::BlockSequenceFirstEntry;
-                Ok((Event::SequenceStart(anchor_id), mark))
+                Ok((Event::SequenceStart(anchor_id, tag), mark))
             },
             Token(mark, TokenType::BlockMappingStart) if block => {
                 self.state = State::BlockMappingFirstKey;
-                Ok((Event::MappingStart(anchor_id), mark))
+                Ok((Event::MappingStart(anchor_id, tag), mark))
             },
             // ex 7.2, an empty scalar can follow a secondary tag
             Token(mark, _) if tag.is_some() || anchor_id > 0 => {
@@ -702,7 +862,7 @@ impl<T: Iterator<Item=char>> Parser<T> {
             Token(mark, TokenType::Key) => {
                 self.state = State::FlowSequenceEntryMappingKey;
                 self.skip();
-                Ok((Event::MappingStart(0), mark))
+                Ok((Event::MappingStart(0, None), mark))
             }
             _ => {
                 self.push_state(State::FlowSequenceEntry);
@@ -818,6 +978,7 @@ impl<T: Iterator<Item=char>> Parser<T> {
 #[cfg(test)]
 mod test {
     use super::{Event, Parser};
+    use scanner::TScalarStyle;
 
     #[test]
     fn test_peek_eq_parse() {
@@ -841,4 +1002,77 @@ a5: *x
             event.0 != Event::StreamEnd
         } {}
     }
+
+    #[test]
+    fn test_max_depth() {
+        let s = "a: [[[[x]]]]\nb: {c: {d: [y]}}\n";
+        let mut p = Parser::new(s.chars());
+        p.max_depth(5);
+        while p.next().unwrap().0 != Event::StreamEnd {}
+
+        let mut p = Parser::new(s.chars());
+        p.max_depth(4);
+        let err = loop {
+            match p.next() {
+                Ok((Event::StreamEnd, _)) => panic!("depth limit not enforced"),
+                Ok(_) => {},
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(err.marker().line(), 1);
+        assert_eq!(err.marker().col(), 6);
+    }
+
+    fn parse_json(s: &str) -> Result<Vec<Event>, String> {
+        let mut p = Parser::new(s.chars());
+        p.strict_json(true);
+        let mut events = Vec::new();
+        loop {
+            match p.next() {
+                Ok((Event::StreamEnd, _)) => return Ok(events),
+                Ok((ev, _)) => events.push(ev),
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_strict_json() {
+        for s in &[
+            r#"{"a":1,"b":[true,false,null,-0.5e+3],"c":{},"d":[]}"#,
+            "\t[ \"tab\\tand \\\"quotes\\\" \\/ \\u00e9 \\ud83d\\ude00\" ]\n",
+            "\"top-level string\"",
+            "42",
+            "{\n  \"multi\": [\n    1,\n    2\n  ]\n}\n",
+        ] {
+            assert!(parse_json(s).is_ok(), "{}: {:?}", s, parse_json(s));
+        }
+        let events = parse_json(r#"["\ud83d\ude00"]"#).unwrap();
+        assert_eq!(events[3], Event::Scalar("\u{1f600}".to_owned(), TScalarStyle::DoubleQuoted, 0, None));
+
+        for &(s, err) in &[
+            ("a: 1", "block collections are not allowed in JSON at line 1 column 2"),
+            ("- 1", "block collections are not allowed in JSON at line 1 column 1"),
+            ("{\"a\": 1} # note", "comments are not allowed in JSON at line 1 column 10"),
+            ("--- 1", "directives and document markers are not allowed in JSON at line 1 column 1"),
+            ("[&x 1, *x]", "anchors and aliases are not allowed in JSON at line 1 column 2"),
+            ("[!!str 1]", "tags are not allowed in JSON at line 1 column 2"),
+            ("[yes]", "plain scalars other than null, true, false and numbers are not allowed in JSON at line 1 column 2"),
+            ("[0x1F]", "plain scalars other than null, true, false and numbers are not allowed in JSON at line 1 column 2"),
+            ("['a']", "strings must be double-quoted in JSON at line 1 column 2"),
+            ("{a: 1}", "object keys must be double-quoted strings in JSON at line 1 column 2"),
+            ("{1: 1}", "object keys must be double-quoted strings in JSON at line 1 column 2"),
+            ("{\"a\"}", "expected a double-quoted key at line 1 column 2"),
+            ("{\"a\": 1,}", "trailing commas are not allowed in JSON at line 1 column 9"),
+            ("[1, 2,]", "trailing commas are not allowed in JSON at line 1 column 7"),
+            ("[\"a\": 1]", "single-pair mappings are not allowed in JSON at line 1 column 2"),
+            ("{? \"a\": 1}", "expected a double-quoted key at line 1 column 4"),
+            ("[\"\\x41\"]", "while scanning a quoted scalar, found an escape that is not valid in JSON at line 1 column 2"),
+            ("[\"a\tb\"]", "while scanning a quoted scalar, found a control character that must be escaped in JSON at line 1 column 2"),
+            ("[\"a\nb\"]", "while scanning a quoted scalar, found a control character that must be escaped in JSON at line 1 column 2"),
+            ("", "expected a JSON value at line 1 column 1"),
+        ] {
+            assert_eq!(parse_json(s), Err(err.to_owned()), "{:?}", s);
+        }
+    }
 }
@@ -0,0 +1,390 @@
+//! JSON Patch (RFC 6902) and JSON Merge Patch (RFC 7396) for `Yaml` documents.
+//!
+//! A JSON Patch is a sequence of operations, each a mapping with an `op` of `add`,
+//! `remove`, `replace`, `move`, `copy` or `test`, a `path` given as a JSON Pointer,
+//! and a `value` or `from` as the operation requires. Patch documents are ordinary
+//! YAML, so they can be written in either syntax and loaded with `YamlLoader`.
+//! A patch is applied as a whole: if any operation fails, the target is left as it
+//! was.
+//!
+//! A Merge Patch is a document that mirrors the target: its mapping values replace
+//! or recursively merge into the target's, and `null` values remove keys.
+//!
+//! # Examples
+//!
+//! ```
+//! use yaml_rust::YamlLoader;
+//!
+//! let mut doc = YamlLoader::load_from_str("
+//! spec:
+//!   replicas: 1
+//!   containers: [{name: web, image: 'nginx:1.0'}]
+//! ").unwrap().remove(0);
+//! let patch = YamlLoader::load_from_str("
+//! - {op: test, path: /spec/replicas, value: 1}
+//! - {op: replace, path: /spec/replicas, value: 3}
+//! - {op: add, path: /spec/containers/-, value: {name: log, image: fluentd}}
+//! ").unwrap().remove(0);
+//! doc.apply_patch(&patch).unwrap();
+//! assert_eq!(doc["spec"]["replicas"].as_i64(), Some(3));
+//!
+//! let merge = YamlLoader::load_from_str("spec: {replicas: null, paused: true}").unwrap().remove(0);
+//! doc.merge_patch(&merge);
+//! assert!(doc["spec"]["replicas"].is_badvalue());
+//! assert_eq!(doc["spec"]["containers"][1]["image"].as_str(), Some("fluentd"));
+//!
+//! let bad = YamlLoader::load_from_str("[{op: remove, path: /spec/volumes}]").unwrap().remove(0);
+//! assert_eq!(doc.apply_patch(&bad).unwrap_err().to_string(),
+//!            "operation 0: missing key at /spec/volumes");
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use diff::Differ;
+use pointer::{Pointer, PointerError};
+use yaml::{Hash, Yaml};
+
+/// Why a patch could not be parsed or applied.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub enum PatchError {
+    /// The patch document is not a sequence of valid operations. Holds a
+    /// description of the problem, naming the operation by its index.
+    InvalidPatch(String),
+    /// A pointer of the operation at this index did not resolve.
+    Pointer(usize, PointerError),
+    /// The `test` operation at this index found a different value at the pointer.
+    TestFailed(usize, Pointer),
+}
+
+impl Error for PatchError {
+    fn description(&self) -> &str {
+        match *self {
+            PatchError::InvalidPatch(_) => "invalid patch",
+            PatchError::Pointer(_, ref e) => e.description(),
+            PatchError::TestFailed(..) => "test failed",
+        }
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            PatchError::Pointer(_, ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PatchError::InvalidPatch(ref m) => write!(formatter, "invalid patch: {}", m),
+            PatchError::Pointer(i, ref e) => write!(formatter, "operation {}: {}", i, e),
+            PatchError::TestFailed(i, ref p) => write!(formatter, "operation {}: test failed at {}", i, p),
+        }
+    }
+}
+
+/// One operation of a JSON Patch.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub enum Operation {
+    Add(Pointer, Yaml),
+    Remove(Pointer),
+    Replace(Pointer, Yaml),
+    /// Move the node at the first pointer to the second.
+    Move(Pointer, Pointer),
+    /// Copy the node at the first pointer to the second.
+    Copy(Pointer, Pointer),
+    Test(Pointer, Yaml),
+}
+
+/// A parsed JSON Patch.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub struct Patch {
+    operations: Vec<Operation>,
+}
+
+impl Patch {
+    pub fn new(operations: Vec<Operation>) -> Patch {
+        Patch { operations: operations }
+    }
+
+    /// Parse a patch document, a sequence of operation mappings.
+    pub fn from_yaml(doc: &Yaml) -> Result<Patch, PatchError> {
+        let ops = match *doc.untagged() {
+            Yaml::Array(ref ops) => ops,
+            _ => return Err(PatchError::InvalidPatch("expected a sequence of operations".to_owned())),
+        };
+        let mut operations = Vec::with_capacity(ops.len());
+        for (i, op) in ops.iter().enumerate() {
+            operations.push(try!(parse_operation(i, op)));
+        }
+        Ok(Patch::new(operations))
+    }
+
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Apply every operation to `doc` in turn. On error `doc` is unchanged.
+    pub fn apply(&self, doc: &mut Yaml) -> Result<(), PatchError> {
+        let mut target = doc.clone();
+        for (i, op) in self.operations.iter().enumerate() {
+            try!(apply_operation(&mut target, i, op));
+        }
+        *doc = target;
+        Ok(())
+    }
+}
+
+fn parse_operation(i: usize, op: &Yaml) -> Result<Operation, PatchError> {
+    let invalid = |m: &str| PatchError::InvalidPatch(format!("operation {} {}", i, m));
+    let h = match *op.untagged() {
+        Yaml::Hash(ref h) => h,
+        _ => return Err(invalid("is not a mapping")),
+    };
+    let member = |name: &str| h.get(&Yaml::String(name.to_owned()));
+    let pointer = |name: &str| match member(name).and_then(|p| p.as_str()) {
+        Some(p) => Pointer::parse(p).map_err(|e| PatchError::Pointer(i, e)),
+        None => Err(invalid(&format!("has no string {:?}", name))),
+    };
+    let value = |name: &str| match member(name) {
+        Some(v) => Ok(v.clone()),
+        None => Err(invalid(&format!("has no {:?}", name))),
+    };
+    let name = match member("op").and_then(|o| o.as_str()) {
+        Some(name) => name,
+        None => return Err(invalid("has no string \"op\"")),
+    };
+    Ok(match name {
+        "add" => Operation::Add(try!(pointer("path")), try!(value("value"))),
+        "remove" => Operation::Remove(try!(pointer("path"))),
+        "replace" => Operation::Replace(try!(pointer("path")), try!(value("value"))),
+        "move" => Operation::Move(try!(pointer("from")), try!(pointer("path"))),
+        "copy" => Operation::Copy(try!(pointer("from")), try!(pointer("path"))),
+        "test" => Operation::Test(try!(pointer("path")), try!(value("value"))),
+        _ => return Err(invalid(&format!("has an unknown op {:?}", name))),
+    })
+}
+
+// Whether `a` and `b` are numbers of the same value, or `None` if either is not
+// a number. An integer equals a float only if the float holds exactly its value.
+fn same_number(a: &Yaml, b: &Yaml) -> Option<bool> {
+    match (a, b) {
+        (&Yaml::Integer(x), &Yaml::Integer(y)) => Some(x == y),
+        // big integers are always written in canonical form, outside the i64 range
+        (&Yaml::BigInteger(ref x), &Yaml::BigInteger(ref y)) => Some(x == y),
+        (&Yaml::Integer(_), &Yaml::BigInteger(_)) | (&Yaml::BigInteger(_), &Yaml::Integer(_)) => Some(false),
+        (&Yaml::Real(_), &Yaml::Real(_)) => Some(a.as_f64().is_some() && a.as_f64() == b.as_f64()),
+        (&Yaml::Real(_), &Yaml::Integer(_)) | (&Yaml::Real(_), &Yaml::BigInteger(_)) => same_number(b, a),
+        (&Yaml::Integer(_), &Yaml::Real(_)) | (&Yaml::BigInteger(_), &Yaml::Real(_)) => {
+            let x = match b.as_f64() {
+                Some(x) if x.fract() == 0.0 => x,
+                _ => return Some(false),
+            };
+            Some(match (a.as_i128(), a) {
+                (Some(n), _) => n as f64 == x && x as i128 == n,
+                (None, &Yaml::BigInteger(ref n)) => n.parse::<f64>().ok() == Some(x),
+                _ => false,
+            })
+        },
+        _ => None,
+    }
+}
+
+// Whether `a` and `b` are equal as JSON values, as the `test` operation compares
+// them: numbers by value however they are written, and mappings regardless of
+// the order of their keys.
+fn json_equal(a: &Yaml, b: &Yaml) -> bool {
+    if let Some(same) = same_number(a, b) {
+        return same;
+    }
+    match (a, b) {
+        (&Yaml::Array(ref a), &Yaml::Array(ref b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(x, y)| json_equal(x, y))
+        },
+        (&Yaml::Hash(ref a), &Yaml::Hash(ref b)) => {
+            a.len() == b.len() && a.iter().all(|(k, v)| b.get(k).map_or(false, |w| json_equal(v, w)))
+        },
+        _ => Differ::new().diff(a, b).is_empty(),
+    }
+}
+
+fn apply_operation(doc: &mut Yaml, i: usize, op: &Operation) -> Result<(), PatchError> {
+    let failed = |e| PatchError::Pointer(i, e);
+    match *op {
+        Operation::Add(ref path, ref value) => {
+            try!(path.insert(doc, value.clone()).map_err(failed));
+        },
+        Operation::Remove(ref path) => {
+            try!(path.remove(doc).map_err(failed));
+        },
+        Operation::Replace(ref path, ref value) => {
+            *try!(path.get_mut(doc).map_err(failed)) = value.clone();
+        },
+        Operation::Move(ref from, ref path) => {
+            let (f, p) = (from.tokens(), path.tokens());
+            if p.len() > f.len() && p.starts_with(f) {
+                return Err(PatchError::InvalidPatch(
+                    format!("operation {} moves {} into its own child {}", i, from, path)));
+            }
+            let value = try!(from.remove(doc).map_err(failed));
+            try!(path.insert(doc, value).map_err(failed));
+        },
+        Operation::Copy(ref from, ref path) => {
+            let value = try!(from.get(doc).map_err(failed)).clone();
+            try!(path.insert(doc, value).map_err(failed));
+        },
+        Operation::Test(ref path, ref value) => {
+            let found = try!(path.get(doc).map_err(failed));
+            if !json_equal(found, value) {
+                return Err(PatchError::TestFailed(i, path.clone()));
+            }
+        },
+    }
+    Ok(())
+}
+
+impl Yaml {
+    /// Apply the JSON Patch document `patch`. See `Patch`.
+    pub fn apply_patch(&mut self, patch: &Yaml) -> Result<(), PatchError> {
+        try!(Patch::from_yaml(patch)).apply(self)
+    }
+
+    /// Apply the JSON Merge Patch `patch`: each value of a mapping patch is merged
+    /// into the target's value for the same key, or removes the key if it is null,
+    /// and any other patch replaces the target. A target that is not a mapping is
+    /// replaced by an empty one before a mapping patch is merged into it. Existing
+    /// keys keep their position.
+    pub fn merge_patch(&mut self, patch: &Yaml) {
+        let patch = match *patch {
+            Yaml::Hash(ref h) => h,
+            _ => {
+                *self = patch.clone();
+                return;
+            },
+        };
+        if self.as_hash().is_none() {
+            *self = Yaml::Hash(Hash::new());
+        }
+        if let Yaml::Hash(ref mut h) = *self {
+            for (k, v) in patch {
+                if v.is_null() {
+                    h.remove(k);
+                    continue;
+                }
+                if let Some(target) = h.get_mut(k) {
+                    target.merge_patch(v);
+                    continue;
+                }
+                let mut value = Yaml::Null;
+                value.merge_patch(v);
+                h.insert(k.clone(), value);
+            }
+        }
+    }
+
+    /// Apply the JSON Merge Patch `patch` to the node at the JSON Pointer `pointer`.
+    pub fn merge_patch_at(&mut self, pointer: &str, patch: &Yaml) -> Result<(), PointerError> {
+        try!(self.pointer_mut(pointer)).merge_patch(patch);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yaml::YamlLoader;
+
+    fn load(s: &str) -> Yaml {
+        YamlLoader::load_from_str(s).unwrap().remove(0)
+    }
+
+    fn patched(doc: &str, patch: &str) -> Result<Yaml, PatchError> {
+        let mut doc = load(doc);
+        try!(doc.apply_patch(&load(patch)));
+        Ok(doc)
+    }
+
+    #[test]
+    fn test_operations() {
+        assert_eq!(patched("{a: 1}", "[{op: add, path: /b, value: [x]}, {op: add, path: /b/0, value: w}]"),
+                   Ok(load("{a: 1, b: [w, x]}")));
+        assert_eq!(patched("{a: 1, b: 2}", "[{op: remove, path: /a}]"), Ok(load("{b: 2}")));
+        assert_eq!(patched("{a: 1, b: 2}", "[{op: replace, path: /a, value: {c: 3}}]"),
+                   Ok(load("{a: {c: 3}, b: 2}")));
+        assert_eq!(patched("{a: {x: 1}, b: []}", "[{op: move, from: /a/x, path: /b/-}]"),
+                   Ok(load("{a: {}, b: [1]}")));
+        assert_eq!(patched("{a: [1, 2]}", "[{op: copy, from: /a, path: /c}]"),
+                   Ok(load("{a: [1, 2], c: [1, 2]}")));
+        assert_eq!(patched("{a: {x: 1.0, y: 2}}", r#"[{"op": "test", "path": "/a", "value": {"y": 2, "x": 1}}]"#),
+                   Ok(load("{a: {x: 1.0, y: 2}}")));
+        assert_eq!(patched("{a: [1, 2.5]}", "[{op: test, path: /a, value: [1, 2]}]"),
+                   Err(PatchError::TestFailed(0, Pointer::parse("/a").unwrap())));
+        assert_eq!(patched("{a: 9007199254740993}", "[{op: test, path: /a, value: 9007199254740992.0}]"),
+                   Err(PatchError::TestFailed(0, Pointer::parse("/a").unwrap())));
+        assert_eq!(patched("{a: 18446744073709551616}", "[{op: test, path: /a, value: 1.8446744073709551616e19}]"),
+                   Ok(load("{a: 18446744073709551616}")));
+        assert_eq!(patched("{a: '1'}", "[{op: test, path: /a, value: 1}]"),
+                   Err(PatchError::TestFailed(0, Pointer::parse("/a").unwrap())));
+        assert_eq!(patched("{a: {x: 1.0, y: 2}}", "[{op: test, path: /a, value: {y: 2, x: 1.00}}]"),
+                   Ok(load("{a: {x: 1.0, y: 2}}")));
+        assert_eq!(patched("[1]", "[{op: replace, path: '', value: 0}]"), Ok(load("0")));
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases = [
+            ("{a: 1}", "{op: add}", "invalid patch: expected a sequence of operations"),
+            ("{a: 1}", "[{op: add, path: /b}]", "invalid patch: operation 0 has no \"value\""),
+            ("{a: 1}", "[{op: remove}]", "invalid patch: operation 0 has no string \"path\""),
+            ("{a: 1}", "[{op: frob, path: /a}]", "invalid patch: operation 0 has an unknown op \"frob\""),
+            ("{a: 1}", "[{op: remove, path: a}]", "operation 0: invalid JSON pointer \"a\""),
+            ("{a: {b: 1}}", "[{op: move, from: /a, path: /a/b/c}]",
+             "invalid patch: operation 0 moves /a into its own child /a/b/c"),
+            ("{a: [1]}", "[{op: test, path: /a/0, value: 1}, {op: replace, path: /a/1, value: 2}]",
+             "operation 1: index out of range at /a/1"),
+            ("{a: 1}", "[{op: add, path: /b/c, value: 2}]", "operation 0: missing key at /b"),
+            ("{a: 1}", "[{op: test, path: /a, value: '1'}]", "operation 0: test failed at /a"),
+        ];
+        for &(doc, patch, err) in &cases {
+            assert_eq!(patched(doc, patch).unwrap_err().to_string(), err, "{}", patch);
+        }
+
+        // a failed patch leaves the document unchanged
+        let mut doc = load("{a: 1}");
+        assert!(doc.apply_patch(&load("[{op: remove, path: /a}, {op: remove, path: /a}]")).is_err());
+        assert_eq!(doc, load("{a: 1}"));
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        // examples from RFC 7396, appendix A
+        let cases = [
+            ("{a: b}", "{a: c}", "{a: c}"),
+            ("{a: b}", "{b: c}", "{a: b, b: c}"),
+            ("{a: b}", "{a: null}", "{}"),
+            ("{a: b, b: c}", "{a: null}", "{b: c}"),
+            ("{a: [b]}", "{a: c}", "{a: c}"),
+            ("{a: c}", "{a: [b]}", "{a: [b]}"),
+            ("{a: {b: c}}", "{a: {b: d, c: null}}", "{a: {b: d}}"),
+            ("{a: [{b: c}]}", "{a: [1]}", "{a: [1]}"),
+            ("[a, b]", "[c, d]", "[c, d]"),
+            ("{a: b}", "[c]", "[c]"),
+            ("{a: foo}", "null", "null"),
+            ("{a: foo}", "bar", "bar"),
+            ("{e: null}", "{a: 1}", "{e: null, a: 1}"),
+            ("[1, 2]", "{a: b, c: null}", "{a: b}"),
+            ("{}", "{a: {bb: {ccc: null}}}", "{a: {bb: {}}}"),
+        ];
+        for &(doc, patch, result) in &cases {
+            let mut doc = load(doc);
+            doc.merge_patch(&load(patch));
+            assert_eq!(doc, load(result), "{}", patch);
+        }
+
+        let mut doc = load("{x: 1, spec: {a: 1, b: 2}}");
+        doc.merge_patch_at("/spec", &load("{a: 3}")).unwrap();
+        assert_eq!(doc, load("{x: 1, spec: {a: 3, b: 2}}"));
+        assert_eq!(doc.merge_patch_at("/status", &load("{a: 3}")),
+                   Err(PointerError::MissingKey("/status".to_owned())));
+    }
+}
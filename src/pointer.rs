@@ -0,0 +1,362 @@
+//! JSON Pointer (RFC 6901) addressing of `Yaml` nodes, e.g. `/spec/containers/0/image`.
+//!
+//! Each `/`-separated token selects a mapping value by key or a sequence element by
+//! index. Within a token, `~1` stands for `/` and `~0` for `~`. A token that is not a
+//! string key of a mapping also matches an integer key with the same digits.
+//!
+//! # Examples
+//!
+//! ```
+//! use yaml_rust::{Yaml, YamlLoader};
+//! use yaml_rust::pointer::PointerError;
+//!
+//! let mut doc = YamlLoader::load_from_str("spec: {containers: [{image: nginx}]}").unwrap().remove(0);
+//! assert_eq!(doc.pointer("/spec/containers/0/image").unwrap().as_str(), Some("nginx"));
+//!
+//! doc.pointer_insert("/spec/containers/-", Yaml::from_str("fluentd")).unwrap();
+//! assert_eq!(doc.pointer_remove("/spec/containers/1").unwrap().as_str(), Some("fluentd"));
+//! assert_eq!(doc.pointer("/spec/volumes"),
+//!            Err(PointerError::MissingKey("/spec/volumes".to_owned())));
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use yaml::{Hash, Yaml};
+
+/// Why a pointer could not be parsed or followed. Every variant except
+/// `InvalidPointer` holds the pointer up to and including the failing token.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub enum PointerError {
+    /// The pointer is neither empty nor starts with `/`, or it contains a `~` that
+    /// is not followed by `0` or `1`. Holds the whole pointer.
+    InvalidPointer(String),
+    /// A mapping has no value for the key.
+    MissingKey(String),
+    /// A sequence index is past the end.
+    IndexOutOfRange(String),
+    /// A token used to index a sequence is not a number, or has leading zeros.
+    InvalidIndex(String),
+    /// A token was applied to a node that is neither a mapping nor a sequence.
+    NotAContainer(String),
+}
+
+impl Error for PointerError {
+    fn description(&self) -> &str {
+        match *self {
+            PointerError::InvalidPointer(_) => "invalid JSON pointer",
+            PointerError::MissingKey(_) => "missing key",
+            PointerError::IndexOutOfRange(_) => "index out of range",
+            PointerError::InvalidIndex(_) => "invalid sequence index",
+            PointerError::NotAContainer(_) => "not a mapping or a sequence",
+        }
+    }
+}
+
+impl fmt::Display for PointerError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PointerError::InvalidPointer(ref p) => write!(formatter, "invalid JSON pointer {:?}", p),
+            PointerError::MissingKey(ref p) => write!(formatter, "missing key at {}", p),
+            PointerError::IndexOutOfRange(ref p) => write!(formatter, "index out of range at {}", p),
+            PointerError::InvalidIndex(ref p) => write!(formatter, "invalid sequence index at {}", p),
+            PointerError::NotAContainer(ref p) => {
+                write!(formatter, "not a mapping or a sequence at {}", p)
+            },
+        }
+    }
+}
+
+type Failure = fn(String) -> PointerError;
+
+/// A parsed JSON Pointer.
+#[derive(Clone, PartialEq, Debug, Eq, Hash)]
+pub struct Pointer {
+    tokens: Vec<String>,
+}
+
+impl Pointer {
+    /// The pointer to the root of a document, `""`.
+    pub fn root() -> Pointer {
+        Pointer { tokens: Vec::new() }
+    }
+
+    pub fn parse(pointer: &str) -> Result<Pointer, PointerError> {
+        if pointer.is_empty() {
+            return Ok(Pointer::root());
+        }
+        if !pointer.starts_with('/') {
+            return Err(PointerError::InvalidPointer(pointer.to_owned()));
+        }
+        let mut tokens = Vec::new();
+        for raw in pointer[1..].split('/') {
+            let mut token = String::with_capacity(raw.len());
+            let mut chars = raw.chars();
+            while let Some(c) = chars.next() {
+                if c != '~' {
+                    token.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('0') => token.push('~'),
+                    Some('1') => token.push('/'),
+                    _ => return Err(PointerError::InvalidPointer(pointer.to_owned())),
+                }
+            }
+            tokens.push(token);
+        }
+        Ok(Pointer { tokens: tokens })
+    }
+
+    /// The unescaped reference tokens.
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+
+    pub fn push(&mut self, token: &str) {
+        self.tokens.push(token.to_owned());
+    }
+
+    pub fn pop(&mut self) -> Option<String> {
+        self.tokens.pop()
+    }
+
+    // The escaped pointer made of the first `n` tokens.
+    fn prefix(&self, n: usize) -> String {
+        let mut s = String::new();
+        for token in &self.tokens[..n] {
+            s.push('/');
+            s.push_str(&token.replace('~', "~0").replace('/', "~1"));
+        }
+        s
+    }
+
+    pub fn get<'a>(&self, root: &'a Yaml) -> Result<&'a Yaml, PointerError> {
+        let mut node = root;
+        for (i, token) in self.tokens.iter().enumerate() {
+            node = try!(child(node, token).map_err(|e| e(self.prefix(i + 1))));
+        }
+        Ok(node)
+    }
+
+    pub fn get_mut<'a>(&self, root: &'a mut Yaml) -> Result<&'a mut Yaml, PointerError> {
+        let mut node = root;
+        for (i, token) in self.tokens.iter().enumerate() {
+            node = try!(child_mut(node, token).map_err(|e| e(self.prefix(i + 1))));
+        }
+        Ok(node)
+    }
+
+    /// Add `value` at this pointer, like the JSON Patch `add` operation: a mapping
+    /// value is inserted or replaced, and a sequence element is inserted before the
+    /// given index, or appended for the token `-`. The parent must exist. Returns
+    /// the value that was replaced, if any.
+    pub fn insert(&self, root: &mut Yaml, value: Yaml) -> Result<Option<Yaml>, PointerError> {
+        let (last, parent) = match self.tokens.split_last() {
+            Some((last, parent)) => (last, parent),
+            None => return Ok(Some(mem::replace(root, value))),
+        };
+        let parent = try!(Pointer { tokens: parent.to_vec() }.get_mut(root));
+        let failed = |e: Failure| e(self.prefix(self.tokens.len()));
+        match *untagged_mut(parent) {
+            Yaml::Hash(ref mut h) | Yaml::OrderedMap(ref mut h) => {
+                let key = hash_key(h, last).unwrap_or_else(|| Yaml::String(last.clone()));
+                Ok(h.insert(key, value))
+            },
+            Yaml::Array(ref mut v) => {
+                let len = v.len();
+                let idx = if last == "-" {
+                    len
+                } else {
+                    try!(array_index(last, len + 1).map_err(failed))
+                };
+                v.insert(idx, value);
+                Ok(None)
+            },
+            _ => Err(failed(PointerError::NotAContainer)),
+        }
+    }
+
+    /// Remove and return the node at this pointer. Removing the root leaves
+    /// `Yaml::Null` in its place.
+    pub fn remove(&self, root: &mut Yaml) -> Result<Yaml, PointerError> {
+        let (last, parent) = match self.tokens.split_last() {
+            Some((last, parent)) => (last, parent),
+            None => return Ok(mem::replace(root, Yaml::Null)),
+        };
+        let parent = try!(Pointer { tokens: parent.to_vec() }.get_mut(root));
+        let failed = |e: Failure| e(self.prefix(self.tokens.len()));
+        match *untagged_mut(parent) {
+            Yaml::Hash(ref mut h) | Yaml::OrderedMap(ref mut h) => {
+                match hash_key(h, last) {
+                    Some(key) => Ok(h.remove(&key).unwrap()),
+                    None => Err(failed(PointerError::MissingKey)),
+                }
+            },
+            Yaml::Array(ref mut v) => {
+                let idx = try!(array_index(last, v.len()).map_err(failed));
+                Ok(v.remove(idx))
+            },
+            _ => Err(failed(PointerError::NotAContainer)),
+        }
+    }
+}
+
+impl fmt::Display for Pointer {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.prefix(self.tokens.len()))
+    }
+}
+
+fn untagged_mut(node: &mut Yaml) -> &mut Yaml {
+    match *node {
+        Yaml::Tagged(_, ref mut v) => v,
+        ref mut v => v,
+    }
+}
+
+// The key of `h` that `token` refers to, if any.
+fn hash_key(h: &Hash, token: &str) -> Option<Yaml> {
+    let key = Yaml::String(token.to_owned());
+    if h.contains_key(&key) {
+        return Some(key);
+    }
+    match token.parse::<i64>() {
+        Ok(n) if h.contains_key(&Yaml::Integer(n)) => Some(Yaml::Integer(n)),
+        _ => None,
+    }
+}
+
+// The index `token` refers to in a sequence of which the first `len` positions are valid.
+fn array_index(token: &str, len: usize) -> Result<usize, Failure> {
+    if token.is_empty() || !token.chars().all(|c| c.is_digit(10)) || (token.len() > 1 && token.starts_with('0')) {
+        // `-` is the position past the last element, which never exists
+        return Err(if token == "-" { PointerError::IndexOutOfRange } else { PointerError::InvalidIndex });
+    }
+    match token.parse::<usize>() {
+        Ok(idx) if idx < len => Ok(idx),
+        _ => Err(PointerError::IndexOutOfRange),
+    }
+}
+
+fn child<'a>(node: &'a Yaml, token: &str) -> Result<&'a Yaml, Failure> {
+    match *node.untagged() {
+        Yaml::Hash(ref h) | Yaml::OrderedMap(ref h) => match hash_key(h, token) {
+            Some(key) => Ok(&h[&key]),
+            None => Err(PointerError::MissingKey),
+        },
+        Yaml::Array(ref v) => Ok(&v[try!(array_index(token, v.len()))]),
+        _ => Err(PointerError::NotAContainer),
+    }
+}
+
+fn child_mut<'a>(node: &'a mut Yaml, token: &str) -> Result<&'a mut Yaml, Failure> {
+    match *untagged_mut(node) {
+        Yaml::Hash(ref mut h) | Yaml::OrderedMap(ref mut h) => match hash_key(h, token) {
+            Some(key) => Ok(h.get_mut(&key).unwrap()),
+            None => Err(PointerError::MissingKey),
+        },
+        Yaml::Array(ref mut v) => {
+            let idx = try!(array_index(token, v.len()));
+            Ok(&mut v[idx])
+        },
+        _ => Err(PointerError::NotAContainer),
+    }
+}
+
+impl Yaml {
+    /// The node at the JSON Pointer `pointer`.
+    pub fn pointer(&self, pointer: &str) -> Result<&Yaml, PointerError> {
+        try!(Pointer::parse(pointer)).get(self)
+    }
+
+    /// The node at the JSON Pointer `pointer`, mutably.
+    pub fn pointer_mut(&mut self, pointer: &str) -> Result<&mut Yaml, PointerError> {
+        try!(Pointer::parse(pointer)).get_mut(self)
+    }
+
+    /// Add `value` at the JSON Pointer `pointer`. See `Pointer::insert`.
+    pub fn pointer_insert(&mut self, pointer: &str, value: Yaml) -> Result<Option<Yaml>, PointerError> {
+        try!(Pointer::parse(pointer)).insert(self, value)
+    }
+
+    /// Remove the node at the JSON Pointer `pointer`. See `Pointer::remove`.
+    pub fn pointer_remove(&mut self, pointer: &str) -> Result<Yaml, PointerError> {
+        try!(Pointer::parse(pointer)).remove(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yaml::YamlLoader;
+
+    #[test]
+    fn test_get() {
+        let s = r#"
+foo: [bar, baz]
+"": 0
+a/b: 1
+"c%d": 2
+"m~n": 4
+1: one
+list: !custom [x]
+"#;
+        let docs = YamlLoader::load_from_str(s).unwrap();
+        let doc = &docs[0];
+        assert_eq!(doc.pointer(""), Ok(doc));
+        assert_eq!(doc.pointer("/foo/0").unwrap().as_str(), Some("bar"));
+        assert_eq!(doc.pointer("/").unwrap().as_i64(), Some(0));
+        assert_eq!(doc.pointer("/a~1b").unwrap().as_i64(), Some(1));
+        assert_eq!(doc.pointer("/c%d").unwrap().as_i64(), Some(2));
+        assert_eq!(doc.pointer("/m~0n").unwrap().as_i64(), Some(4));
+        assert_eq!(doc.pointer("/1").unwrap().as_str(), Some("one"));
+        assert_eq!(doc.pointer("/list/0").unwrap().as_str(), Some("x"));
+
+        assert_eq!(doc.pointer("foo"), Err(PointerError::InvalidPointer("foo".to_owned())));
+        assert_eq!(doc.pointer("/m~2n"), Err(PointerError::InvalidPointer("/m~2n".to_owned())));
+        assert_eq!(doc.pointer("/nope/x"), Err(PointerError::MissingKey("/nope".to_owned())));
+        assert_eq!(doc.pointer("/foo/2"), Err(PointerError::IndexOutOfRange("/foo/2".to_owned())));
+        assert_eq!(doc.pointer("/foo/-"), Err(PointerError::IndexOutOfRange("/foo/-".to_owned())));
+        assert_eq!(doc.pointer("/foo/01"), Err(PointerError::InvalidIndex("/foo/01".to_owned())));
+        assert_eq!(doc.pointer("/foo/0/x"), Err(PointerError::NotAContainer("/foo/0/x".to_owned())));
+        assert_eq!(doc.pointer("/a~1b/x").unwrap_err().to_string(),
+                   "not a mapping or a sequence at /a~1b/x");
+    }
+
+    #[test]
+    fn test_modify() {
+        let mut doc = YamlLoader::load_from_str("{a: {b: [1, 2]}, c: 3}").unwrap().remove(0);
+        *doc.pointer_mut("/a/b/1").unwrap() = Yaml::Integer(20);
+        assert_eq!(doc.pointer_insert("/a/b/0", Yaml::Integer(0)), Ok(None));
+        assert_eq!(doc.pointer_insert("/a/b/-", Yaml::Integer(30)), Ok(None));
+        assert_eq!(doc.pointer_insert("/c", Yaml::Integer(4)), Ok(Some(Yaml::Integer(3))));
+        assert_eq!(doc.pointer_insert("/d", Yaml::Null), Ok(None));
+        assert_eq!(doc, YamlLoader::load_from_str("{a: {b: [0, 1, 20, 30]}, c: 4, d: ~}").unwrap()[0]);
+
+        assert_eq!(doc.pointer_insert("/a/b/5", Yaml::Null),
+                   Err(PointerError::IndexOutOfRange("/a/b/5".to_owned())));
+        assert_eq!(doc.pointer_insert("/x/y", Yaml::Null), Err(PointerError::MissingKey("/x".to_owned())));
+        assert_eq!(doc.pointer_insert("/c/y", Yaml::Null), Err(PointerError::NotAContainer("/c/y".to_owned())));
+
+        assert_eq!(doc.pointer_remove("/a/b/0"), Ok(Yaml::Integer(0)));
+        assert_eq!(doc.pointer_remove("/c"), Ok(Yaml::Integer(4)));
+        assert_eq!(doc.pointer_remove("/c"), Err(PointerError::MissingKey("/c".to_owned())));
+        assert_eq!(doc.pointer_remove("/a/b/3"), Err(PointerError::IndexOutOfRange("/a/b/3".to_owned())));
+        assert_eq!(doc, YamlLoader::load_from_str("{a: {b: [1, 20, 30]}, d: ~}").unwrap()[0]);
+
+        let old = doc.pointer_insert("", Yaml::Integer(1)).unwrap();
+        assert!(old.unwrap().as_hash().is_some());
+        assert_eq!(doc.pointer_remove(""), Ok(Yaml::Integer(1)));
+        assert_eq!(doc, Yaml::Null);
+    }
+
+    #[test]
+    fn test_display() {
+        let mut p = Pointer::parse("/a~1b/m~0n").unwrap();
+        assert_eq!(p.tokens(), &["a/b".to_owned(), "m~n".to_owned()]);
+        p.push("0");
+        assert_eq!(p.to_string(), "/a~1b/m~0n/0");
+        assert_eq!(Pointer::root().to_string(), "");
+    }
+}
@@ -0,0 +1,777 @@
+//! JSONPath-style queries over `Yaml` documents.
+//!
+//! A query is a sequence of selectors, optionally starting with `$`:
+//!
+//! * `.name`, `['name']` or `["name"]` select a mapping value by key,
+//! * `[2]` selects a sequence element (negative indices count from the end),
+//! * `.*` and `[*]` select every element of a sequence or value of a mapping,
+//! * `[1:5:2]` selects a slice of a sequence,
+//! * `['a', 'b', 0]` selects the union of several names and indices,
+//! * `..name`, `..*` and `..[0]` apply a selector to a node and all its descendants,
+//! * `[?(@.name == "web" && @.port > 80)]` keeps the elements or values for which a
+//!   filter holds. Filters compare relative paths (`@.a.b`, `@['a'][0]`) with each
+//!   other or with literal strings, numbers, `true`, `false` and `null`, test that a
+//!   path exists (`@.image`), and combine with `!`, `&&`, `||` and parentheses.
+//!
+//! # Examples
+//!
+//! ```
+//! use yaml_rust::YamlLoader;
+//!
+//! let docs = YamlLoader::load_from_str("
+//! spec:
+//!   containers:
+//!   - {name: web, image: nginx}
+//!   - {name: log, image: fluentd}
+//! ").unwrap();
+//! let images = docs[0].query("spec.containers[*].image").unwrap();
+//! assert_eq!(images.len(), 2);
+//! assert_eq!(images[0].as_str().unwrap(), "nginx");
+//!
+//! let web = docs[0].query_paths("$..[?(@.name == 'web')]").unwrap();
+//! assert_eq!(web[0].0.to_string(), "spec.containers[0]");
+//! ```
+
+use std::cmp::{self, Ordering};
+use std::error::Error;
+use std::fmt;
+use yaml::Yaml;
+
+/// One step of a `Path`: a mapping key or a sequence index.
+#[derive(Clone, PartialEq, PartialOrd, Debug, Eq, Ord, Hash)]
+pub enum PathSegment {
+    Key(Yaml),
+    Index(usize),
+}
+
+/// The location of a node within a document, e.g. `servers[2].port`.
+///
+/// The root of the document is the empty path, which displays as an empty string.
+#[derive(Clone, PartialEq, PartialOrd, Debug, Eq, Ord, Hash)]
+pub struct Path {
+    segments: Vec<PathSegment>,
+}
+
+impl Path {
+    pub fn new() -> Path {
+        Path { segments: Vec::new() }
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn push(&mut self, segment: PathSegment) {
+        self.segments.push(segment);
+    }
+
+    pub fn pop(&mut self) -> Option<PathSegment> {
+        self.segments.pop()
+    }
+
+    /// A copy of this path extended by `segment`.
+    pub fn child(&self, segment: PathSegment) -> Path {
+        let mut path = self.clone();
+        path.push(segment);
+        path
+    }
+
+    /// Follow this path from `root`, returning `None` if a step is missing.
+    pub fn get<'a>(&self, root: &'a Yaml) -> Option<&'a Yaml> {
+        let mut node = root;
+        for segment in &self.segments {
+            node = match (segment, node.untagged()) {
+                (&PathSegment::Key(ref k), &Yaml::Hash(ref h))
+                    | (&PathSegment::Key(ref k), &Yaml::OrderedMap(ref h)) => {
+                    match h.get(k) {
+                        Some(v) => v,
+                        None => return None,
+                    }
+                },
+                (&PathSegment::Index(i), &Yaml::Array(ref v)) => {
+                    match v.get(i) {
+                        Some(v) => v,
+                        None => return None,
+                    }
+                },
+                _ => return None,
+            };
+        }
+        Some(node)
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            match *segment {
+                PathSegment::Key(Yaml::String(ref k)) if !k.is_empty() && k.chars().all(is_name_char) => {
+                    if i > 0 {
+                        try!(f.write_str("."));
+                    }
+                    try!(f.write_str(k));
+                },
+                PathSegment::Key(Yaml::String(ref k)) => try!(write!(f, "[{:?}]", k)),
+                PathSegment::Key(Yaml::Integer(k)) => try!(write!(f, "[{}]", k)),
+                PathSegment::Key(ref k) => try!(write!(f, "[{:?}]", k)),
+                PathSegment::Index(i) => try!(write!(f, "[{}]", i)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A malformed query expression.
+#[derive(Clone, PartialEq, Debug, Eq)]
+pub struct QueryError {
+    pos: usize,
+    info: String,
+}
+
+impl QueryError {
+    fn new(pos: usize, info: &str) -> QueryError {
+        QueryError {
+            pos: pos,
+            info: info.to_owned(),
+        }
+    }
+
+    /// Byte offset of the problem in the expression.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+impl Error for QueryError {
+    fn description(&self) -> &str {
+        self.info.as_ref()
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        None
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} at position {}", self.info, self.pos)
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Selector {
+    Name(String),
+    Index(i64),
+    Wildcard,
+    Slice(Option<i64>, Option<i64>, i64),
+    Union(Vec<Selector>),
+    Filter(Filter),
+    Descendants(Box<Selector>),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Path(Vec<Selector>),
+    Literal(Yaml),
+}
+
+#[derive(Clone, Debug)]
+enum Filter {
+    Exists(Vec<Selector>),
+    Compare(Operand, CmpOp, Operand),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+/// A parsed query expression, which can be run against many documents.
+#[derive(Clone, Debug)]
+pub struct Query {
+    selectors: Vec<Selector>,
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos == self.src.len()
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.src[self.pos..].starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn error<T>(&self, info: &str) -> Result<T, QueryError> {
+        Err(QueryError::new(self.pos, info))
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), QueryError> {
+        self.skip_ws();
+        if self.eat(s) {
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", s))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, QueryError> {
+        let len = self.src[self.pos..].find(|c| !is_name_char(c)).unwrap_or(self.src.len() - self.pos);
+        if len == 0 {
+            return self.error("expected a name");
+        }
+        let name = self.src[self.pos..self.pos + len].to_owned();
+        self.pos += len;
+        Ok(name)
+    }
+
+    fn string(&mut self) -> Result<String, QueryError> {
+        let quote = self.peek().unwrap();
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return self.error("unterminated string"),
+            };
+            self.pos += c.len_utf8();
+            if c == quote {
+                return Ok(s);
+            }
+            if c != '\\' {
+                s.push(c);
+                continue;
+            }
+            let escaped = match self.peek() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some(c) if c == '\\' || c == '/' || c == '\'' || c == '"' => c,
+                _ => return self.error("invalid escape sequence"),
+            };
+            self.pos += 1;
+            s.push(escaped);
+        }
+    }
+
+    fn integer(&mut self) -> Result<Option<i64>, QueryError> {
+        self.skip_ws();
+        let start = self.pos;
+        self.eat("-");
+        while self.peek().map_or(false, |c| c.is_digit(10)) {
+            self.pos += 1;
+        }
+        match &self.src[start..self.pos] {
+            "" => Ok(None),
+            digits => match digits.parse() {
+                Ok(n) => Ok(Some(n)),
+                Err(_) => Err(QueryError::new(start, "invalid integer")),
+            },
+        }
+    }
+
+    fn query(&mut self) -> Result<Vec<Selector>, QueryError> {
+        let mut selectors = Vec::new();
+        self.skip_ws();
+        if !self.eat("$") && self.peek().map_or(false, is_name_char) {
+            selectors.push(Selector::Name(try!(self.name())));
+        }
+        loop {
+            self.skip_ws();
+            if self.at_end() {
+                return Ok(selectors);
+            }
+            let selector = if self.eat("..") {
+                let selector = if self.eat("*") {
+                    Selector::Wildcard
+                } else if self.peek() == Some('[') {
+                    try!(self.bracket())
+                } else {
+                    Selector::Name(try!(self.name()))
+                };
+                Selector::Descendants(Box::new(selector))
+            } else if self.eat(".") {
+                if self.eat("*") {
+                    Selector::Wildcard
+                } else {
+                    Selector::Name(try!(self.name()))
+                }
+            } else if self.peek() == Some('[') {
+                try!(self.bracket())
+            } else {
+                return self.error("expected '.' or '['");
+            };
+            selectors.push(selector);
+        }
+    }
+
+    fn bracket(&mut self) -> Result<Selector, QueryError> {
+        try!(self.expect("["));
+        self.skip_ws();
+        let selector = if self.eat("*") {
+            Selector::Wildcard
+        } else if self.eat("?") {
+            try!(self.expect("("));
+            let filter = try!(self.filter_or());
+            try!(self.expect(")"));
+            Selector::Filter(filter)
+        } else {
+            let mut items = vec![try!(self.union_item())];
+            loop {
+                self.skip_ws();
+                if !self.eat(",") {
+                    break;
+                }
+                items.push(try!(self.union_item()));
+            }
+            if items.len() == 1 { items.pop().unwrap() } else { Selector::Union(items) }
+        };
+        try!(self.expect("]"));
+        Ok(selector)
+    }
+
+    fn union_item(&mut self) -> Result<Selector, QueryError> {
+        self.skip_ws();
+        if self.peek() == Some('\'') || self.peek() == Some('"') {
+            return Ok(Selector::Name(try!(self.string())));
+        }
+        let start = try!(self.integer());
+        self.skip_ws();
+        if !self.eat(":") {
+            return match start {
+                Some(i) => Ok(Selector::Index(i)),
+                None => self.error("expected a name, an index or a slice"),
+            };
+        }
+        let end = try!(self.integer());
+        self.skip_ws();
+        let step = if self.eat(":") { try!(self.integer()) } else { None };
+        if step == Some(0) {
+            return self.error("slice step cannot be zero");
+        }
+        Ok(Selector::Slice(start, end, step.unwrap_or(1)))
+    }
+
+    fn filter_or(&mut self) -> Result<Filter, QueryError> {
+        let mut filter = try!(self.filter_and());
+        loop {
+            self.skip_ws();
+            if !self.eat("||") {
+                return Ok(filter);
+            }
+            filter = Filter::Or(Box::new(filter), Box::new(try!(self.filter_and())));
+        }
+    }
+
+    fn filter_and(&mut self) -> Result<Filter, QueryError> {
+        let mut filter = try!(self.filter_unary());
+        loop {
+            self.skip_ws();
+            if !self.eat("&&") {
+                return Ok(filter);
+            }
+            filter = Filter::And(Box::new(filter), Box::new(try!(self.filter_unary())));
+        }
+    }
+
+    fn filter_unary(&mut self) -> Result<Filter, QueryError> {
+        self.skip_ws();
+        if self.eat("!") {
+            return Ok(Filter::Not(Box::new(try!(self.filter_unary()))));
+        }
+        if self.eat("(") {
+            let filter = try!(self.filter_or());
+            try!(self.expect(")"));
+            return Ok(filter);
+        }
+        let start = self.pos;
+        let lhs = try!(self.operand());
+        self.skip_ws();
+        let op = if self.eat("==") {
+            CmpOp::Eq
+        } else if self.eat("!=") {
+            CmpOp::Ne
+        } else if self.eat("<=") {
+            CmpOp::Le
+        } else if self.eat(">=") {
+            CmpOp::Ge
+        } else if self.eat("<") {
+            CmpOp::Lt
+        } else if self.eat(">") {
+            CmpOp::Gt
+        } else {
+            return match lhs {
+                Operand::Path(path) => Ok(Filter::Exists(path)),
+                Operand::Literal(_) => Err(QueryError::new(start, "expected a comparison")),
+            };
+        };
+        Ok(Filter::Compare(lhs, op, try!(self.operand())))
+    }
+
+    fn operand(&mut self) -> Result<Operand, QueryError> {
+        self.skip_ws();
+        match self.peek() {
+            Some('@') => {
+                self.pos += 1;
+                let mut path = Vec::new();
+                loop {
+                    if self.eat(".") {
+                        path.push(Selector::Name(try!(self.name())));
+                    } else if self.peek() == Some('[') {
+                        self.pos += 1;
+                        self.skip_ws();
+                        let item = try!(self.union_item());
+                        match item {
+                            Selector::Name(_) | Selector::Index(_) => path.push(item),
+                            _ => return self.error("expected a name or an index"),
+                        }
+                        try!(self.expect("]"));
+                    } else {
+                        return Ok(Operand::Path(path));
+                    }
+                }
+            },
+            Some('\'') | Some('"') => Ok(Operand::Literal(Yaml::String(try!(self.string())))),
+            Some(c) if c == '-' || c.is_digit(10) => {
+                let start = self.pos;
+                let len = self.src[start..]
+                    .find(|c: char| !(c.is_digit(10) || "+-.eE".contains(c)))
+                    .unwrap_or(self.src.len() - start);
+                self.pos += len;
+                let text = &self.src[start..self.pos];
+                if let Ok(n) = text.parse::<i64>() {
+                    Ok(Operand::Literal(Yaml::Integer(n)))
+                } else if text.parse::<f64>().is_ok() {
+                    Ok(Operand::Literal(Yaml::Real(text.to_owned())))
+                } else {
+                    Err(QueryError::new(start, "invalid number"))
+                }
+            },
+            _ => {
+                let start = self.pos;
+                match self.name().as_ref().map(|s| s.as_ref()) {
+                    Ok("true") => Ok(Operand::Literal(Yaml::Boolean(true))),
+                    Ok("false") => Ok(Operand::Literal(Yaml::Boolean(false))),
+                    Ok("null") => Ok(Operand::Literal(Yaml::Null)),
+                    _ => Err(QueryError::new(start, "expected '@' or a literal")),
+                }
+            },
+        }
+    }
+}
+
+// The children of a collection with their path segments.
+fn children(node: &Yaml) -> Vec<(PathSegment, &Yaml)> {
+    match *node.untagged() {
+        Yaml::Array(ref v) => v.iter().enumerate().map(|(i, x)| (PathSegment::Index(i), x)).collect(),
+        Yaml::Hash(ref h) | Yaml::OrderedMap(ref h) => {
+            h.iter().map(|(k, v)| (PathSegment::Key(k.clone()), v)).collect()
+        },
+        Yaml::Pairs(ref p) => p.iter().map(|&(ref k, ref v)| (PathSegment::Key(k.clone()), v)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn slice_indices(start: Option<i64>, end: Option<i64>, step: i64, len: usize) -> Vec<usize> {
+    let len = len as i64;
+    let normalize = |i: i64| if i >= 0 { i } else { len + i };
+    let mut indices = Vec::new();
+    if step > 0 {
+        let lower = cmp::min(cmp::max(normalize(start.unwrap_or(0)), 0), len);
+        let upper = cmp::min(cmp::max(normalize(end.unwrap_or(len)), 0), len);
+        let mut i = lower;
+        while i < upper {
+            indices.push(i as usize);
+            i = match i.checked_add(step) {
+                Some(n) => n,
+                None => break,
+            };
+        }
+    } else {
+        let upper = cmp::min(cmp::max(normalize(start.unwrap_or(len - 1)), -1), len - 1);
+        let lower = cmp::min(cmp::max(normalize(end.unwrap_or(-len - 1)), -1), len - 1);
+        let mut i = upper;
+        while lower < i {
+            indices.push(i as usize);
+            i = match i.checked_add(step) {
+                Some(n) => n,
+                None => break,
+            };
+        }
+    }
+    indices
+}
+
+impl Selector {
+    fn apply<'a>(&self, path: &Path, node: &'a Yaml, out: &mut Vec<(Path, &'a Yaml)>) {
+        let node = node.untagged();
+        match *self {
+            Selector::Name(ref name) => select_key(path, node, &Yaml::String(name.clone()), out),
+            Selector::Index(i) => {
+                if let Yaml::Array(ref v) = *node {
+                    let idx = if i >= 0 { i } else { v.len() as i64 + i };
+                    if idx >= 0 && (idx as usize) < v.len() {
+                        out.push((path.child(PathSegment::Index(idx as usize)), &v[idx as usize]));
+                    }
+                } else {
+                    select_key(path, node, &Yaml::Integer(i), out);
+                }
+            },
+            Selector::Wildcard => {
+                for (segment, child) in children(node) {
+                    out.push((path.child(segment), child));
+                }
+            },
+            Selector::Slice(start, end, step) => {
+                if let Yaml::Array(ref v) = *node {
+                    for i in slice_indices(start, end, step, v.len()) {
+                        out.push((path.child(PathSegment::Index(i)), &v[i]));
+                    }
+                }
+            },
+            Selector::Union(ref items) => {
+                for item in items {
+                    item.apply(path, node, out);
+                }
+            },
+            Selector::Filter(ref filter) => {
+                for (segment, child) in children(node) {
+                    if filter.matches(child) {
+                        out.push((path.child(segment), child));
+                    }
+                }
+            },
+            Selector::Descendants(ref selector) => {
+                selector.apply(path, node, out);
+                for (segment, child) in children(node) {
+                    self.apply(&path.child(segment), child, out);
+                }
+            },
+        }
+    }
+}
+
+fn select_key<'a>(path: &Path, node: &'a Yaml, key: &Yaml, out: &mut Vec<(Path, &'a Yaml)>) {
+    match *node {
+        Yaml::Hash(ref h) | Yaml::OrderedMap(ref h) => {
+            if let Some(v) = h.get(key) {
+                out.push((path.child(PathSegment::Key(key.clone())), v));
+            }
+        },
+        Yaml::Pairs(ref p) => {
+            for &(ref k, ref v) in p.iter().filter(|&&(ref k, _)| k == key) {
+                out.push((path.child(PathSegment::Key(k.clone())), v));
+            }
+        },
+        _ => {},
+    }
+}
+
+// Follow a relative filter path such as `@.a[0]` from `node`.
+fn resolve<'a>(path: &[Selector], node: &'a Yaml) -> Option<&'a Yaml> {
+    let mut node = node;
+    for selector in path {
+        let mut out = Vec::new();
+        selector.apply(&Path::new(), node, &mut out);
+        node = match out.into_iter().next() {
+            Some((_, n)) => n,
+            None => return None,
+        };
+    }
+    Some(node)
+}
+
+fn as_number(v: &Yaml) -> Option<f64> {
+    match *v {
+        Yaml::Integer(i) => Some(i as f64),
+        Yaml::Real(_) => v.as_f64(),
+        Yaml::BigInteger(ref s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn compare(a: &Yaml, op: CmpOp, b: &Yaml) -> bool {
+    let (a, b) = (a.untagged(), b.untagged());
+    let ord = match (as_number(a), as_number(b), a, b) {
+        (Some(x), Some(y), _, _) => x.partial_cmp(&y),
+        (_, _, &Yaml::String(ref x), &Yaml::String(ref y)) => Some(x.cmp(y)),
+        _ if a == b => Some(Ordering::Equal),
+        _ => None,
+    };
+    match op {
+        CmpOp::Eq => ord == Some(Ordering::Equal),
+        CmpOp::Ne => ord != Some(Ordering::Equal),
+        CmpOp::Lt => ord == Some(Ordering::Less),
+        CmpOp::Le => ord == Some(Ordering::Less) || ord == Some(Ordering::Equal),
+        CmpOp::Gt => ord == Some(Ordering::Greater),
+        CmpOp::Ge => ord == Some(Ordering::Greater) || ord == Some(Ordering::Equal),
+    }
+}
+
+impl Operand {
+    fn value<'a>(&'a self, node: &'a Yaml) -> Option<&'a Yaml> {
+        match *self {
+            Operand::Path(ref path) => resolve(path, node),
+            Operand::Literal(ref v) => Some(v),
+        }
+    }
+}
+
+impl Filter {
+    fn matches(&self, node: &Yaml) -> bool {
+        match *self {
+            Filter::Exists(ref path) => resolve(path, node).is_some(),
+            Filter::Compare(ref a, op, ref b) => match (a.value(node), b.value(node)) {
+                (Some(a), Some(b)) => compare(a, op, b),
+                _ => false,
+            },
+            Filter::Not(ref f) => !f.matches(node),
+            Filter::And(ref a, ref b) => a.matches(node) && b.matches(node),
+            Filter::Or(ref a, ref b) => a.matches(node) || b.matches(node),
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(expr: &str) -> Result<Query, QueryError> {
+        let mut parser = Parser { src: expr, pos: 0 };
+        Ok(Query { selectors: try!(parser.query()) })
+    }
+
+    /// The nodes of `root` matching this query, with their paths, in document order.
+    pub fn select<'a>(&self, root: &'a Yaml) -> Vec<(Path, &'a Yaml)> {
+        let mut current = vec![(Path::new(), root)];
+        for selector in &self.selectors {
+            let mut next = Vec::new();
+            for &(ref path, node) in &current {
+                selector.apply(path, node, &mut next);
+            }
+            current = next;
+        }
+        current
+    }
+}
+
+impl Yaml {
+    /// The nodes matching the JSONPath-style query `expr`. See the `query` module
+    /// for the syntax.
+    pub fn query(&self, expr: &str) -> Result<Vec<&Yaml>, QueryError> {
+        let query = try!(Query::parse(expr));
+        Ok(query.select(self).into_iter().map(|(_, v)| v).collect())
+    }
+
+    /// Like `query`, but also returns the path of each matching node.
+    pub fn query_paths(&self, expr: &str) -> Result<Vec<(Path, &Yaml)>, QueryError> {
+        let query = try!(Query::parse(expr));
+        Ok(query.select(self))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yaml::YamlLoader;
+
+    fn paths(doc: &Yaml, expr: &str) -> Vec<String> {
+        doc.query_paths(expr).unwrap().into_iter().map(|(p, _)| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_selectors() {
+        let s = "
+store:
+  book:
+  - {category: reference, author: Nigel Rees, price: 8.95}
+  - {category: fiction, author: Evelyn Waugh, price: 12.99}
+  - {category: fiction, author: Herman Melville, isbn: 0-553-21311-3, price: 8.99}
+  - {category: fiction, author: J. R. R. Tolkien, isbn: 0-395-19395-8, price: 22.99}
+  bicycle: {color: red, price: 19.95}
+  'odd key': 1
+";
+        let docs = YamlLoader::load_from_str(&s).unwrap();
+        let doc = &docs[0];
+        let authors = doc.query("$.store.book[*].author").unwrap();
+        assert_eq!(authors.len(), 4);
+        assert_eq!(authors[3].as_str().unwrap(), "J. R. R. Tolkien");
+        assert_eq!(doc.query("$..author").unwrap().len(), 4);
+        assert_eq!(paths(doc, "store.*").len(), 3);
+        assert_eq!(paths(doc, "$..price").len(), 5);
+        assert_eq!(paths(doc, "store.book[-1].author"), vec!["store.book[3].author"]);
+        assert_eq!(paths(doc, "store.book[0,2]"), vec!["store.book[0]", "store.book[2]"]);
+        assert_eq!(paths(doc, "store.book[1:3]"), vec!["store.book[1]", "store.book[2]"]);
+        assert_eq!(paths(doc, "store.book[::-2]"), vec!["store.book[3]", "store.book[1]"]);
+        assert_eq!(paths(doc, "store.book[1::9223372036854775807]"), vec!["store.book[1]"]);
+        assert_eq!(paths(doc, "store.book[::-9223372036854775808]"), vec!["store.book[3]"]);
+        assert_eq!(paths(doc, "store['odd key']"), vec!["store[\"odd key\"]"]);
+        assert_eq!(paths(doc, "$['store'][\"bicycle\"].color"), vec!["store.bicycle.color"]);
+        assert_eq!(paths(doc, "$..book[?(@.isbn)].author"),
+                   vec!["store.book[2].author", "store.book[3].author"]);
+        assert_eq!(paths(doc, "$..book[?(@.price < 10)]"), vec!["store.book[0]", "store.book[2]"]);
+        assert_eq!(paths(doc, "$..book[?(@.category == 'fiction' && !(@.price >= 10))]"),
+                   vec!["store.book[2]"]);
+        assert_eq!(paths(doc, "$..book[?(@.author == \"Nigel Rees\" || @['price'] > 20)]"),
+                   vec!["store.book[0]", "store.book[3]"]);
+        assert!(paths(doc, "store.missing[0]").is_empty());
+
+        let (path, node) = doc.query_paths("$..bicycle.price").unwrap().pop().unwrap();
+        assert_eq!(path.get(doc), Some(node));
+        assert_eq!(Path::new().get(doc), Some(doc));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for &(expr, msg) in &[
+            ("store.", "expected a name at position 6"),
+            ("store[0", "expected ']' at position 7"),
+            ("store[?(@.a ==)]", "expected '@' or a literal at position 14"),
+            ("store['a]", "unterminated string at position 9"),
+            ("store[::0]", "slice step cannot be zero at position 9"),
+            ("store[?(1)]", "expected a comparison at position 8"),
+            ("store x", "expected '.' or '[' at position 6"),
+        ] {
+            let err = Query::parse(expr).unwrap_err();
+            assert_eq!(err.to_string(), msg);
+        }
+        // whitespace outside ASCII is skipped a whole character at a time
+        let doc = &YamlLoader::load_from_str("store: {a: 1}\na: [2]").unwrap()[0];
+        assert_eq!(paths(doc, "store\u{3000}.a"), vec!["store.a"]);
+        assert_eq!(paths(doc, "a[\u{a0}0]"), vec!["a[0]"]);
+        assert_eq!(Query::parse("a[0]\u{2003}x").unwrap_err().to_string(),
+                   "expected '.' or '[' at position 7");
+    }
+}
@@ -25,8 +25,11 @@ pub struct Marker {
     col: usize,
 }
 
+// A placeholder position for nodes that have no place in the source.
+pub(crate) const ORIGIN: Marker = Marker { index: 0, line: 0, col: 0 };
+
 impl Marker {
-    fn new(index: usize, line: usize, col: usize) -> Marker {
+    pub fn new(index: usize, line: usize, col: usize) -> Marker {
         Marker {
             index: index,
             line: line,
@@ -140,6 +143,10 @@ pub struct Scanner<T> {
     rdr: T,
     mark: Marker,
     tokens: VecDeque<Token>,
+    // start and end marks of the scalar tokens scanned since `track_scalar_ends`
+    // was enabled and not yet claimed, in order
+    scalar_ends: VecDeque<(Marker, Marker)>,
+    track_scalar_ends: bool,
     buffer: VecDeque<char>,
     error: Option<ScanError>,
 
@@ -152,6 +159,7 @@ pub struct Scanner<T> {
     flow_level: usize,
     tokens_parsed: usize,
     token_available: bool,
+    json: bool,
 }
 
 impl<T: Iterator<Item=char>> Iterator for Scanner<T> {
@@ -202,6 +210,49 @@ fn is_alpha(c: char) -> bool {
         _ => false
     }
 }
+/// Whether `v` is a number as the JSON grammar defines it.
+pub fn is_json_number(v: &str) -> bool {
+    let v = v.as_bytes();
+    let mut i = 0;
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while *i < v.len() && v[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        *i > start
+    };
+    if v.get(i) == Some(&b'-') {
+        i += 1;
+    }
+    if v.get(i) == Some(&b'0') {
+        i += 1;
+    } else if !digits(&mut i) {
+        return false;
+    }
+    if v.get(i) == Some(&b'.') {
+        i += 1;
+        if !digits(&mut i) {
+            return false;
+        }
+    }
+    if v.get(i) == Some(&b'e') || v.get(i) == Some(&b'E') {
+        i += 1;
+        if v.get(i) == Some(&b'+') || v.get(i) == Some(&b'-') {
+            i += 1;
+        }
+        if !digits(&mut i) {
+            return false;
+        }
+    }
+    i == v.len()
+}
+#[inline]
+fn is_json_escape(c: char) -> bool {
+    match c {
+        '"' | '\\' | '/' | 'b' | 'f' | 'n' | 'r' | 't' | 'u' => true,
+        _ => false,
+    }
+}
 #[inline]
 fn is_hex(c: char) -> bool {
     (c >= '0' && c <= '9')
@@ -228,6 +279,8 @@ impl<T: Iterator<Item=char>> Scanner<T> {
             buffer: VecDeque::new(),
             mark: Marker::new(0, 1, 0),
             tokens: VecDeque::new(),
+            scalar_ends: VecDeque::new(),
+            track_scalar_ends: false,
             error: None,
 
             stream_start_produced: false,
@@ -239,8 +292,15 @@ impl<T: Iterator<Item=char>> Scanner<T> {
             flow_level: 0,
             tokens_parsed: 0,
             token_available: false,
+            json: false,
         }
     }
+    /// Reject comments, and double-quoted strings with escapes, tabs or line
+    /// breaks that JSON does not allow. Tabs are accepted between tokens.
+    /// See `Parser::strict_json` for the checks on the token stream.
+    pub fn strict_json(&mut self, strict: bool) {
+        self.json = strict;
+    }
     #[inline]
     pub fn get_error(&self) -> Option<ScanError> {
         match self.error {
@@ -304,6 +364,35 @@ impl<T: Iterator<Item=char>> Scanner<T> {
     pub fn mark(&self) -> Marker {
         self.mark
     }
+    /// Record where each scalar token scanned from now on ends, for
+    /// `scalar_end`. Off by default, as the records are only released by
+    /// `scalar_end`.
+    pub fn track_scalar_ends(&mut self, track: bool) {
+        self.track_scalar_ends = track;
+        if !track {
+            self.scalar_ends.clear();
+        }
+    }
+    /// Marker just past the source text of the scalar token starting at `start`,
+    /// if it was scanned while `track_scalar_ends` was enabled. Records of
+    /// earlier scalars are dropped.
+    pub fn scalar_end(&mut self, start: Marker) -> Option<Marker> {
+        while let Some(&(s, end)) = self.scalar_ends.front() {
+            if s.index() > start.index() {
+                break;
+            }
+            self.scalar_ends.pop_front();
+            if s.index() == start.index() {
+                return Some(end);
+            }
+        }
+        None
+    }
+    fn push_scalar_end(&mut self, start: Marker, end: Marker) {
+        if self.track_scalar_ends {
+            self.scalar_ends.push_back((start, end));
+        }
+    }
     #[inline]
     fn read_break(&mut self, s: &mut String) {
         if self.buffer[0] == '\r' && self.buffer[1] == '\n' {
@@ -340,7 +429,7 @@ impl<T: Iterator<Item=char>> Scanner<T> {
             self.fetch_stream_start();
             return Ok(());
         }
-        self.skip_to_next_token();
+        try!(self.skip_to_next_token());
 
         try!(self.stale_simple_keys());
 
@@ -463,13 +552,13 @@ impl<T: Iterator<Item=char>> Scanner<T> {
         Ok(())
     }
 
-    fn skip_to_next_token(&mut self) {
+    fn skip_to_next_token(&mut self) -> ScanResult {
         loop {
             self.lookahead(1);
             // TODO(chenyh) BOM
             match self.ch() {
                 ' ' => self.skip(),
-                '\t' if self.flow_level > 0 || !self.simple_key_allowed => self.skip(),
+                '\t' if self.json || self.flow_level > 0 || !self.simple_key_allowed => self.skip(),
                 '\n' | '\r' => {
                     self.lookahead(2);
                     self.skip_line();
@@ -477,10 +566,14 @@ impl<T: Iterator<Item=char>> Scanner<T> {
                         self.allow_simple_key();
                     }
                 },
+                '#' if self.json => {
+                    return Err(ScanError::new(self.mark, "comments are not allowed in JSON"));
+                },
                 '#' => while !is_breakz(self.ch()) { self.skip(); self.lookahead(1); },
                 _ => break
             }
         }
+        Ok(())
     }
 
     fn fetch_stream_start(&mut self) {
@@ -1087,6 +1180,7 @@ impl<T: Iterator<Item=char>> Scanner<T> {
         self.lookahead(1);
 
         let start_mark = self.mark;
+        let mut end_mark = self.mark;
 
         while self.mark.col == indent && !is_z(self.ch()) {
             // We are at the beginning of a non-empty line.
@@ -1112,6 +1206,7 @@ impl<T: Iterator<Item=char>> Scanner<T> {
                 self.skip();
                 self.lookahead(1);
             }
+            end_mark = self.mark;
             // break on EOF
             if is_z(self.ch()) { break; }
 
@@ -1131,6 +1226,7 @@ impl<T: Iterator<Item=char>> Scanner<T> {
             string.push_str(&trailing_breaks);
         }
 
+        self.push_scalar_end(start_mark, end_mark);
         if literal {
             Ok(Token(start_mark, TokenType::Scalar(TScalarStyle::Literal, string)))
         } else {
@@ -1239,6 +1335,10 @@ impl<T: Iterator<Item=char>> Scanner<T> {
                     // Check for the right quote.
                     '\'' if single => { break; },
                     '"' if !single => { break; },
+                    '\\' if self.json && !single && !is_json_escape(self.buffer[1]) => {
+                        return Err(ScanError::new(start_mark,
+                            "while scanning a quoted scalar, found an escape that is not valid in JSON"));
+                    },
                     // Check for an escaped line break.
                     '\\' if !single && is_break(self.buffer[1]) => {
                         self.lookahead(3);
@@ -1264,6 +1364,7 @@ impl<T: Iterator<Item=char>> Scanner<T> {
                             '"' => string.push('"'),
                             '\'' => string.push('\''),
                             '\\' => string.push('\\'),
+                            '/' => string.push('/'),
                             // NEL (#x85)
                             'N' => string.push(char::from_u32(0x85).unwrap()),
                             // #xA0
@@ -1291,6 +1392,25 @@ impl<T: Iterator<Item=char>> Scanner<T> {
                                 }
                                 value = (value << 4) + as_hex(self.buffer[i]);
                             }
+                            for _ in 0..code_length {
+                                self.skip();
+                            }
+
+                            // a UTF-16 surrogate pair, as JSON escapes characters outside the BMP
+                            if code_length == 4 && value >= 0xD800 && value < 0xDC00 {
+                                self.lookahead(6);
+                                if self.buffer[0] == '\\' && self.buffer[1] == 'u'
+                                    && self.buffer.iter().skip(2).take(4).all(|&c| is_hex(c)) {
+                                    let low = self.buffer.iter().skip(2).take(4)
+                                        .fold(0, |v, &c| (v << 4) + as_hex(c));
+                                    if low >= 0xDC00 && low < 0xE000 {
+                                        value = 0x10000 + ((value - 0xD800) << 10) + (low - 0xDC00);
+                                        for _ in 0..6 {
+                                            self.skip();
+                                        }
+                                    }
+                                }
+                            }
 
                             let ch = match char::from_u32(value) {
                                 Some(v) => v,
@@ -1300,12 +1420,12 @@ impl<T: Iterator<Item=char>> Scanner<T> {
                                 }
                             };
                             string.push(ch);
-
-                            for _ in 0..code_length {
-                                self.skip();
-                            }
                         }
                     },
+                    c if self.json && c < ' ' => {
+                        return Err(ScanError::new(start_mark,
+                            "while scanning a quoted scalar, found a control character that must be escaped in JSON"));
+                    },
                     c => { string.push(c); self.skip(); }
                 }
                 self.lookahead(2);
@@ -1319,6 +1439,10 @@ impl<T: Iterator<Item=char>> Scanner<T> {
 
             // Consume blank characters.
             while is_blank(self.ch()) || is_break(self.ch()) {
+                if self.json && self.ch() != ' ' {
+                    return Err(ScanError::new(start_mark,
+                        "while scanning a quoted scalar, found a control character that must be escaped in JSON"));
+                }
                 if is_blank(self.ch()) {
                     // Consume a space or a tab character.
                     if leading_blanks {
@@ -1364,6 +1488,8 @@ impl<T: Iterator<Item=char>> Scanner<T> {
 
         // Eat the right quote.
         self.skip();
+        let end_mark = self.mark;
+        self.push_scalar_end(start_mark, end_mark);
 
         if single {
             Ok(Token(start_mark, TokenType::Scalar(TScalarStyle::SingleQuoted, string)))
@@ -1385,6 +1511,7 @@ impl<T: Iterator<Item=char>> Scanner<T> {
     fn scan_plain_scalar(&mut self) -> Result<Token, ScanError> {
         let indent = self.indent + 1;
         let start_mark = self.mark;
+        let mut end_mark = self.mark;
 
         let mut string = String::new();
         let mut leading_break = String::new();
@@ -1449,6 +1576,7 @@ impl<T: Iterator<Item=char>> Scanner<T> {
                 self.skip();
                 self.lookahead(2);
             }
+            end_mark = self.mark;
             // is the end?
             if !(is_blank(self.ch()) || is_break(self.ch())) { break; }
             self.lookahead(1);
@@ -1491,6 +1619,7 @@ impl<T: Iterator<Item=char>> Scanner<T> {
             self.allow_simple_key();
         }
 
+        self.push_scalar_end(start_mark, end_mark);
         Ok(Token(start_mark, TokenType::Scalar(TScalarStyle::Plain, string)))
     }
 
@@ -2000,4 +2129,26 @@ key:
     fn test_uri_escapes() {
         // TODO
     }
+
+    #[test]
+    fn test_scalar_ends() {
+        let s = "a: 'b'\nc: |\n  d\n";
+        // nothing is kept unless asked for
+        let mut p = Scanner::new(s.chars());
+        assert_eq!(p.by_ref().count(), 12);
+        assert!(p.scalar_ends.is_empty());
+
+        let mut p = Scanner::new(s.chars());
+        let first: Vec<Token> = p.by_ref().take(4).collect();
+        p.track_scalar_ends(true);
+        let rest: Vec<Token> = p.by_ref().collect();
+        // `a` was scanned before tracking started
+        assert_eq!(p.scalar_end(first[3].0), None);
+        let ends: Vec<usize> = rest.iter().filter_map(|t| match *t {
+            Token(mark, Scalar(..)) => Some(mark),
+            _ => None,
+        }).map(|mark| p.scalar_end(mark).unwrap().index()).collect();
+        assert_eq!(ends, vec![6, 8, 15]);
+        assert!(p.scalar_ends.is_empty());
+    }
 }
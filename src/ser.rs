@@ -0,0 +1,494 @@
+//! Serialization of Rust values to YAML with serde, enabled by the `serde` feature.
+//!
+//! `Serializer` turns a value into a `Yaml` tree, which `to_string` and
+//! `to_emitter` then write with a `YamlEmitter`. Unit variants of enums become
+//! plain scalars, and variants with data become nodes with a local tag, such as
+//! `!Circle {r: 1.5}`, which `de::from_str` reads back. Byte buffers become
+//! `!!binary` scalars.
+//!
+//! # Examples
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! # extern crate yaml_rust;
+//! # fn main() {
+//! #[derive(Serialize)]
+//! enum Shape {
+//!     Circle { r: f64 },
+//!     Point,
+//! }
+//!
+//! let shapes = vec![Shape::Circle { r: 1.5 }, Shape::Point];
+//! assert_eq!(yaml_rust::ser::to_string(&shapes).unwrap(), "---
+//! - !Circle
+//!   r: 1.5
+//! - Point");
+//! # }
+//! ```
+
+use std::error;
+use std::fmt;
+use std::i64;
+use serde::ser::{self, Serialize};
+use emitter::{EmitError, YamlEmitter};
+use yaml::{Array, Hash, Tag, Yaml};
+
+/// An error while serializing: either the value could not be represented, or
+/// the emitter failed.
+#[derive(Clone, Debug)]
+pub enum Error {
+    Custom(String),
+    Emit(EmitError),
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Custom(ref msg) => msg,
+            Error::Emit(ref err) => err.description(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Custom(ref msg) => formatter.write_str(msg),
+            Error::Emit(ref err) => fmt::Display::fmt(err, formatter),
+        }
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Error {
+        Error::Custom(msg.to_string())
+    }
+}
+
+impl From<EmitError> for Error {
+    fn from(err: EmitError) -> Error {
+        Error::Emit(err)
+    }
+}
+
+/// Convert `value` into a `Yaml` tree.
+pub fn to_yaml<T: Serialize + ?Sized>(value: &T) -> Result<Yaml, Error> {
+    value.serialize(Serializer)
+}
+
+/// Write `value` as a YAML document with `emitter`.
+pub fn to_emitter<T: Serialize + ?Sized>(emitter: &mut YamlEmitter, value: &T) -> Result<(), Error> {
+    let doc = try!(to_yaml(value));
+    try!(emitter.dump(&doc));
+    Ok(())
+}
+
+/// Serialize `value` as a YAML document.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    let mut out = String::new();
+    {
+        let mut emitter = YamlEmitter::new(&mut out);
+        try!(to_emitter(&mut emitter, value));
+    }
+    Ok(out)
+}
+
+fn tagged(variant: &str, value: Yaml) -> Yaml {
+    Yaml::Tagged(Tag::new("!", variant), Box::new(value))
+}
+
+/// A serializer producing a `Yaml` tree.
+#[derive(Clone, Copy, Debug)]
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Yaml;
+    type Error = Error;
+    type SerializeSeq = SerializeArray;
+    type SerializeTuple = SerializeArray;
+    type SerializeTupleStruct = SerializeArray;
+    type SerializeTupleVariant = SerializeArray;
+    type SerializeMap = SerializeHash;
+    type SerializeStruct = SerializeHash;
+    type SerializeStructVariant = SerializeHash;
+
+    fn serialize_bool(self, v: bool) -> Result<Yaml, Error> {
+        Ok(Yaml::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Yaml, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Yaml, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Yaml, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Yaml, Error> {
+        Ok(Yaml::Integer(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Yaml, Error> {
+        if v >= i128::from(i64::MIN) && v <= i128::from(i64::MAX) {
+            Ok(Yaml::Integer(v as i64))
+        } else {
+            Ok(Yaml::BigInteger(v.to_string()))
+        }
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Yaml, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Yaml, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Yaml, Error> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Yaml, Error> {
+        self.serialize_u128(u128::from(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Yaml, Error> {
+        if v <= i64::MAX as u128 {
+            Ok(Yaml::Integer(v as i64))
+        } else {
+            Ok(Yaml::BigInteger(v.to_string()))
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Yaml, Error> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Yaml, Error> {
+        let text = if v.is_nan() {
+            ".nan".to_owned()
+        } else if v.is_infinite() {
+            if v > 0.0 { ".inf" } else { "-.inf" }.to_owned()
+        } else {
+            // Debug keeps a fractional part, so the value reloads as a float
+            format!("{:?}", v)
+        };
+        Ok(Yaml::Real(text))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Yaml, Error> {
+        Ok(Yaml::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Yaml, Error> {
+        Ok(Yaml::String(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Yaml, Error> {
+        Ok(Yaml::Binary(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Yaml, Error> {
+        Ok(Yaml::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Yaml, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Yaml, Error> {
+        Ok(Yaml::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Yaml, Error> {
+        Ok(Yaml::Null)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str)
+        -> Result<Yaml, Error> {
+        Ok(Yaml::String(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T)
+        -> Result<Yaml, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32,
+                                                        variant: &'static str, value: &T)
+        -> Result<Yaml, Error> {
+        Ok(tagged(variant, try!(value.serialize(self))))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray, Error> {
+        Ok(SerializeArray {
+            variant: None,
+            array: Array::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeArray, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str,
+                               len: usize) -> Result<SerializeArray, Error> {
+        Ok(SerializeArray {
+            variant: Some(variant),
+            array: Array::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeHash, Error> {
+        Ok(SerializeHash {
+            variant: None,
+            hash: Hash::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeHash, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str,
+                                _len: usize) -> Result<SerializeHash, Error> {
+        Ok(SerializeHash {
+            variant: Some(variant),
+            hash: Hash::new(),
+            key: None,
+        })
+    }
+}
+
+/// Builds a sequence, tagged with the variant for tuple variants.
+pub struct SerializeArray {
+    variant: Option<&'static str>,
+    array: Array,
+}
+
+impl SerializeArray {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.array.push(try!(value.serialize(Serializer)));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Yaml, Error> {
+        let array = Yaml::Array(self.array);
+        Ok(match self.variant {
+            Some(variant) => tagged(variant, array),
+            None => array,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SerializeArray {
+    type Ok = Yaml;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Yaml, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SerializeArray {
+    type Ok = Yaml;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Yaml, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeArray {
+    type Ok = Yaml;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Yaml, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeArray {
+    type Ok = Yaml;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Yaml, Error> {
+        self.finish()
+    }
+}
+
+/// Builds a mapping, tagged with the variant for struct variants.
+pub struct SerializeHash {
+    variant: Option<&'static str>,
+    hash: Hash,
+    // the key whose value is serialized next
+    key: Option<Yaml>,
+}
+
+impl SerializeHash {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: Yaml, value: &T) -> Result<(), Error> {
+        self.hash.insert(key, try!(value.serialize(Serializer)));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Yaml, Error> {
+        let hash = Yaml::Hash(self.hash);
+        Ok(match self.variant {
+            Some(variant) => tagged(variant, hash),
+            None => hash,
+        })
+    }
+}
+
+impl ser::SerializeMap for SerializeHash {
+    type Ok = Yaml;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(try!(key.serialize(Serializer)));
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        match self.key.take() {
+            Some(key) => self.insert(key, value),
+            None => Err(Error::Custom("serialize_value called before serialize_key".to_owned())),
+        }
+    }
+
+    fn end(self) -> Result<Yaml, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for SerializeHash {
+    type Ok = Yaml;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T)
+        -> Result<(), Error> {
+        self.insert(Yaml::String(key.to_owned()), value)
+    }
+
+    fn end(self) -> Result<Yaml, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeHash {
+    type Ok = Yaml;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T)
+        -> Result<(), Error> {
+        self.insert(Yaml::String(key.to_owned()), value)
+    }
+
+    fn end(self) -> Result<Yaml, Error> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use super::*;
+    use de::from_str;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Action {
+        Stop,
+        Wait(u32),
+        Move(i32, i32),
+        Say { text: String },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Plan {
+        name: String,
+        ratio: f64,
+        whole: f64,
+        steps: Vec<Action>,
+        limits: BTreeMap<String, u64>,
+        data: Option<Vec<u8>>,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut limits = BTreeMap::new();
+        limits.insert("big".to_owned(), u64::max_value());
+        limits.insert("small".to_owned(), 1);
+        let plan = Plan {
+            name: "true".to_owned(),
+            ratio: 0.25,
+            whole: 2.0,
+            steps: vec![
+                Action::Stop,
+                Action::Wait(3),
+                Action::Move(1, -1),
+                Action::Say { text: "hi".to_owned() },
+            ],
+            limits: limits,
+            data: None,
+            note: Some("multi\nline".to_owned()),
+        };
+        let out = to_string(&plan).unwrap();
+        assert_eq!(out, "---
+name: \"true\"
+ratio: 0.25
+whole: 2.0
+steps:
+  - Stop
+  - !Wait 3
+  - !Move
+    - 1
+    - -1
+  - !Say
+    text: hi
+limits:
+  big: 18446744073709551615
+  small: 1
+data: ~
+note: \"multi\\nline\"");
+        assert_eq!(from_str::<Plan>(&out).unwrap(), plan);
+    }
+
+    #[test]
+    fn test_to_yaml() {
+        assert_eq!(to_yaml(&(1u8, 'c', "s", ())).unwrap(), Yaml::Array(vec![
+            Yaml::Integer(1),
+            Yaml::String("c".to_owned()),
+            Yaml::String("s".to_owned()),
+            Yaml::Null,
+        ]));
+        assert_eq!(to_yaml(&::std::f64::NAN).unwrap(), Yaml::Real(".nan".to_owned()));
+        assert_eq!(to_yaml(&-1e300).unwrap(), Yaml::Real("-1e300".to_owned()));
+        assert_eq!(Yaml::from_str("-1e300").as_f64(), Some(-1e300));
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt;
+
+macro_rules! try_opt {
+    ($e:expr) => (match $e { Some(v) => v, None => return None })
+}
+
+/// A YAML timestamp (`tag:yaml.org,2002:timestamp`), either a date such as
+/// `2002-12-14` or a point in time such as `2001-12-14t21:59:43.10-05:00`.
+///
+/// The fields hold the timestamp as written; use `to_utc` to normalize the
+/// offset away. Displaying a timestamp gives its canonical form.
+///
+/// # Examples
+///
+/// ```
+/// use yaml_rust::timestamp::Timestamp;
+///
+/// let t = Timestamp::parse("2001-12-14t21:59:43.10-05:00").unwrap();
+/// assert_eq!(t.hour, 21);
+/// assert_eq!(t.offset, Some(-5 * 60));
+/// assert_eq!(t.to_string(), "2001-12-15T02:59:43.1Z");
+/// ```
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Eq, Ord, Hash)]
+pub struct Timestamp {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub nanosecond: u32,
+    /// Offset from UTC in minutes, or `None` for a date without a time of day.
+    pub offset: Option<i32>,
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let y = i64::from(if month <= 2 { year - 1 } else { year });
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let m = i64::from(month);
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// Inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i32, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = (yoe + era * 400) as i32 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// A cursor over the text of a timestamp.
+struct Reader<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Reader<'a> {
+    // Read between `min` and `max` ASCII digits.
+    fn digits(&mut self, min: usize, max: usize) -> Option<u32> {
+        let len = self.rest.bytes().take(max).take_while(|b| b.is_ascii_digit()).count();
+        if len < min {
+            return None;
+        }
+        let (digits, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        digits.parse().ok()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.rest.starts_with(c) {
+            self.rest = &self.rest[c.len_utf8()..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_blanks(&mut self) -> bool {
+        let trimmed = self.rest.trim_start_matches(|c| c == ' ' || c == '\t');
+        let skipped = trimmed.len() != self.rest.len();
+        self.rest = trimmed;
+        skipped
+    }
+}
+
+impl Timestamp {
+    /// Parse a timestamp matching the regular expression of the YAML timestamp type
+    /// (http://yaml.org/type/timestamp.html). Returns `None` for any other text or
+    /// for dates and times out of range, such as February 30th.
+    pub fn parse(v: &str) -> Option<Timestamp> {
+        let mut r = Reader { rest: v };
+        let year = try_opt!(r.digits(4, 4));
+        if !r.eat('-') {
+            return None;
+        }
+        let month = try_opt!(r.digits(1, 2));
+        if !r.eat('-') {
+            return None;
+        }
+        let day = try_opt!(r.digits(1, 2));
+        let year = year as i32;
+        if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+        let mut t = Timestamp {
+            year: year,
+            month: month,
+            day: day,
+            hour: 0,
+            minute: 0,
+            second: 0,
+            nanosecond: 0,
+            offset: None,
+        };
+        if r.rest.is_empty() {
+            // the date-only form needs exactly two digit months and days
+            return if v.len() == 10 { Some(t) } else { None };
+        }
+
+        if !(r.eat('T') || r.eat('t') || r.skip_blanks()) {
+            return None;
+        }
+        t.hour = try_opt!(r.digits(1, 2));
+        if !r.eat(':') {
+            return None;
+        }
+        t.minute = try_opt!(r.digits(2, 2));
+        if !r.eat(':') {
+            return None;
+        }
+        t.second = try_opt!(r.digits(2, 2));
+        if t.hour > 23 || t.minute > 59 || t.second > 59 {
+            return None;
+        }
+        if r.eat('.') {
+            let len = r.rest.bytes().take_while(|b| b.is_ascii_digit()).count();
+            let (fraction, rest) = r.rest.split_at(len);
+            // keep nanosecond precision, truncating any further digits
+            let mut nanos = 0;
+            for b in fraction.bytes().chain("000000000".bytes()).take(9) {
+                nanos = nanos * 10 + u32::from(b - b'0');
+            }
+            t.nanosecond = nanos;
+            r.rest = rest;
+        }
+
+        r.skip_blanks();
+        t.offset = Some(0);
+        if r.eat('Z') {
+            // UTC
+        } else if r.rest.starts_with('+') || r.rest.starts_with('-') {
+            let sign = if r.eat('-') { -1 } else { r.eat('+'); 1 };
+            let hours = try_opt!(r.digits(1, 2));
+            let minutes = if r.eat(':') { try_opt!(r.digits(2, 2)) } else { 0 };
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            t.offset = Some(sign * (hours * 60 + minutes) as i32);
+        } else if v.ends_with(|c| c == ' ' || c == '\t') {
+            return None;
+        }
+        if r.rest.is_empty() { Some(t) } else { None }
+    }
+
+    /// Whether this is a date without a time of day, such as `2002-12-14`.
+    pub fn is_date(&self) -> bool {
+        self.offset.is_none()
+    }
+
+    /// The same point in time with a zero offset. Dates are returned unchanged.
+    pub fn to_utc(&self) -> Timestamp {
+        let offset = match self.offset {
+            Some(offset) if offset != 0 => i64::from(offset),
+            _ => return *self,
+        };
+        let minutes = days_from_civil(self.year, self.month, self.day) * 1440
+            + i64::from(self.hour * 60 + self.minute) - offset;
+        let days = if minutes >= 0 { minutes / 1440 } else { (minutes - 1439) / 1440 };
+        let (year, month, day) = civil_from_days(days);
+        let minute_of_day = (minutes - days * 1440) as u32;
+        Timestamp {
+            year: year,
+            month: month,
+            day: day,
+            hour: minute_of_day / 60,
+            minute: minute_of_day % 60,
+            second: self.second,
+            nanosecond: self.nanosecond,
+            offset: Some(0),
+        }
+    }
+
+    /// Seconds since 1970-01-01T00:00:00Z, ignoring the fractional part.
+    /// Dates count from midnight UTC.
+    pub fn unix_timestamp(&self) -> i64 {
+        let t = self.to_utc();
+        days_from_civil(t.year, t.month, t.day) * 86_400
+            + i64::from(t.hour * 3600 + t.minute * 60 + t.second)
+    }
+}
+
+impl fmt::Display for Timestamp {
+    /// Write the canonical form: `2002-12-14` for dates, and the time in UTC,
+    /// e.g. `2001-12-15T02:59:43.1Z`, otherwise.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_date() {
+            return write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day);
+        }
+        let t = self.to_utc();
+        try!(write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                    t.year, t.month, t.day, t.hour, t.minute, t.second));
+        if t.nanosecond != 0 {
+            let fraction = format!("{:09}", t.nanosecond);
+            try!(write!(f, ".{}", fraction.trim_end_matches('0')));
+        }
+        f.write_str("Z")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let t = Timestamp::parse("2001-12-14t21:59:43.10-05:00").unwrap();
+        assert_eq!((t.year, t.month, t.day), (2001, 12, 14));
+        assert_eq!((t.hour, t.minute, t.second, t.nanosecond), (21, 59, 43, 100_000_000));
+        assert_eq!(t.offset, Some(-300));
+
+        let t = Timestamp::parse("2001-12-14 21:59:43.10 -5").unwrap();
+        assert_eq!(t.offset, Some(-300));
+        let t = Timestamp::parse("2001-12-15 2:59:43.1234567891").unwrap();
+        assert_eq!((t.hour, t.nanosecond, t.offset), (2, 123_456_789, Some(0)));
+        let t = Timestamp::parse("2001-12-15T02:59:43.1Z").unwrap();
+        assert_eq!(t.offset, Some(0));
+        let t = Timestamp::parse("2002-12-14").unwrap();
+        assert!(t.is_date());
+        assert!(Timestamp::parse("2000-02-29").is_some());
+
+        for v in &["2002-12-1", "2001-2-3", "1900-02-29", "2002-13-01", "20021-12-14",
+                   "2001-12-14T25:00:00Z", "2001-12-14T21:59", "2001-12-14T21:59:43+",
+                   "2001-12-14T21:59:43 ", "2001-12-14T21:59:43 X", "2001-12-14x"] {
+            assert_eq!(Timestamp::parse(v), None, "{}", v);
+        }
+    }
+
+    #[test]
+    fn test_canonical() {
+        for &(v, canonical) in &[
+            ("2001-12-14t21:59:43.10-05:00", "2001-12-15T02:59:43.1Z"),
+            ("2001-12-14 21:59:43.10 -5", "2001-12-15T02:59:43.1Z"),
+            ("2001-12-15 2:59:43.10", "2001-12-15T02:59:43.1Z"),
+            ("2000-03-01T01:00:00+02:30", "2000-02-29T22:30:00Z"),
+            ("1999-12-31T23:30:00-01:00", "2000-01-01T00:30:00Z"),
+            ("2002-12-14", "2002-12-14"),
+        ] {
+            assert_eq!(Timestamp::parse(v).unwrap().to_string(), canonical);
+        }
+        let t = Timestamp::parse("1970-01-01T01:00:00+01:00").unwrap();
+        assert_eq!(t.unix_timestamp(), 0);
+        assert_eq!(Timestamp::parse("2001-12-15").unwrap().unix_timestamp(), 1_008_374_400);
+    }
+}
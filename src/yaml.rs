@@ -1,13 +1,19 @@
-use std::collections::BTreeMap;
-use std::ops::Index;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::ops::{Index, IndexMut};
 use std::string;
 use std::i64;
 use std::f64;
 use std::mem;
 use std::vec;
 use parser::*;
-use scanner::{TScalarStyle, ScanError, TokenType, Marker};
-use linked_hash_map::LinkedHashMap;
+use scanner::{TScalarStyle, ScanError, TokenType, Marker, ORIGIN};
+use timestamp::Timestamp;
+use base64;
+use linked_hash_map::{Entry, LinkedHashMap};
+#[cfg(feature = "num-bigint")]
+use num_bigint::BigInt;
 
 /// A YAML node is stored as this `Yaml` enumeration, which provides an easy way to
 /// access your YAML document.
@@ -32,8 +38,23 @@ pub enum Yaml {
     Real(string::String),
     /// YAML int is stored as i64.
     Integer(i64),
+    /// YAML int outside the range of i64, stored as its decimal digits with an
+    /// optional leading `-`. Read it with `as_u64`, `as_u128` and friends.
+    BigInteger(string::String),
+    /// YAML timestamp, e.g. `2001-12-14t21:59:43.10-05:00` or `2002-12-14`.
+    Timestamp(Timestamp),
     /// YAML scalar.
     String(string::String),
+    /// YAML binary data, decoded from a base64 scalar tagged `!!binary`.
+    Binary(Vec<u8>),
+    /// YAML set, loaded from a mapping tagged `!!set` whose values are all null.
+    Set(self::Set),
+    /// YAML ordered map, loaded from a sequence of single-pair mappings tagged
+    /// `!!omap`. Its keys are unique.
+    OrderedMap(self::Hash),
+    /// YAML pairs, loaded from a sequence of single-pair mappings tagged `!!pairs`.
+    /// Unlike `OrderedMap`, keys may repeat.
+    Pairs(self::Pairs),
     /// YAML bool, e.g. `true` or `false`.
     Boolean(bool),
     /// YAML array, can be accessed as a `Vec`.
@@ -42,8 +63,21 @@ pub enum Yaml {
     ///
     /// Itertion order will match the order of insertion into the map.
     Hash(self::Hash),
-    /// Alias, not fully supported yet.
+    /// Reference to an anchored node, by anchor id.
+    ///
+    /// Only produced by a `YamlLoader` with alias expansion disabled; the node can
+    /// be looked up in the loader's `AnchorMap` with `resolve` or `expand`.
+    ///
+    /// An alias does not carry the node it refers to: indexing, the `as_*`
+    /// accessors, queries, pointers and diffs see the alias itself, as they would
+    /// any other scalar. Call `expand` first to work on a self-contained tree. To
+    /// write the document back with its aliases, give the anchors to
+    /// `YamlEmitter::anchors`.
     Alias(usize),
+    /// A node with an explicit tag that the loader does not resolve, e.g. `!Ref foo`.
+    /// Only produced by a `YamlLoader` with tag preservation enabled. Tagged scalars
+    /// hold their raw text as a `Yaml::String`.
+    Tagged(Tag, Box<Yaml>),
     /// YAML null, e.g. `null` or `~`.
     Null,
     /// Accessing a nonexistent node via the Index trait returns `BadValue`. This
@@ -52,8 +86,68 @@ pub enum Yaml {
     BadValue,
 }
 
+/// Error returned by the checked integer accessors such as `Yaml::checked_u64`.
+#[derive(Clone, Copy, PartialEq, Debug, Eq)]
+pub enum IntegerError {
+    /// The node is not an integer.
+    NotAnInteger,
+    /// The node is an integer outside the range of the requested type.
+    Overflow,
+}
+
+impl Error for IntegerError {
+    fn description(&self) -> &str {
+        match *self {
+            IntegerError::NotAnInteger => "not an integer",
+            IntegerError::Overflow => "integer out of range",
+        }
+    }
+}
+
+impl fmt::Display for IntegerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// A node tag, split as written into its handle and suffix: `!Ref` has handle `!`
+/// and suffix `Ref`, `!!set` has handle `!!` and suffix `set`. Verbatim tags such
+/// as `!<tag:yaml.org,2002:str>` have an empty handle.
+#[derive(Clone, PartialEq, PartialOrd, Debug, Eq, Ord, Hash)]
+pub struct Tag {
+    pub handle: String,
+    pub suffix: String,
+}
+
+impl Tag {
+    pub fn new(handle: &str, suffix: &str) -> Tag {
+        Tag {
+            handle: handle.to_owned(),
+            suffix: suffix.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.handle.is_empty() && self.suffix != "!" {
+            write!(f, "!<{}>", self.suffix)
+        } else {
+            write!(f, "{}{}", self.handle, self.suffix)
+        }
+    }
+}
+
 pub type Array = Vec<Yaml>;
 pub type Hash = LinkedHashMap<Yaml, Yaml>;
+pub type Set = LinkedHashMap<Yaml, ()>;
+pub type Pairs = Vec<(Yaml, Yaml)>;
+/// Anchored nodes of a stream, keyed by the anchor id referenced by `Yaml::Alias`.
+pub type AnchorMap = BTreeMap<usize, Yaml>;
+
+// Placeholder stored in the key stack while the value of a merge key is loaded.
+// Anchor ids start from 1, so this never collides with a real alias.
+const MERGE_KEY: usize = 0;
 
 // parse f64 as Core schema
 // See: https://github.com/chyh1990/yaml-rust/issues/51
@@ -61,23 +155,468 @@ fn parse_f64(v: &str) -> Option<f64> {
     match v {
         ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => Some(f64::INFINITY),
         "-.inf" | "-.Inf" | "-.INF" => Some(f64::NEG_INFINITY),
-        ".nan" | "NaN" | ".NAN" => Some(f64::NAN),
+        ".nan" | "NaN" | ".NaN" | ".NAN" => Some(f64::NAN),
         _ => v.parse::<f64>().ok()
     }
 }
 
+/// Rules for resolving the type of an untagged plain scalar.
+#[derive(Clone, Copy, PartialEq, Debug, Eq)]
+pub enum Schema {
+    /// Every scalar is a string.
+    Failsafe,
+    /// YAML 1.2 JSON schema: only `null`, `true`, `false` and JSON numbers are
+    /// recognized. Other scalars, which the spec rejects, are kept as strings.
+    Json,
+    /// YAML 1.2 Core schema: adds `~`, `Null`/`True`/`FALSE` style capitalizations,
+    /// `+` signs, `0o` and `0x` integers, and `.inf`/`.nan`.
+    Core,
+    /// YAML 1.1 compatibility: adds `yes`/`no`/`on`/`off`, `0b` binary and `0777`
+    /// octal integers, sexagesimal numbers like `1:30:00`, `_` digit separators and
+    /// timestamps. As with most 1.1 parsers, single letter `y` and `n` stay strings.
+    Yaml11,
+}
+
+// An integer written in base `radix`, as `Yaml::Integer` if it fits in an i64 and
+// as `Yaml::BigInteger` otherwise. `digits` must be non-empty and valid in `radix`.
+fn integer(sign: &str, digits: &str, radix: u32) -> Yaml {
+    match i64::from_str_radix(&format!("{}{}", sign, digits), radix) {
+        Ok(n) => Yaml::Integer(n),
+        Err(_) => {
+            let mut decimal = radix_to_decimal(digits, radix);
+            if sign == "-" {
+                decimal.insert(0, '-');
+            }
+            Yaml::BigInteger(decimal)
+        }
+    }
+}
+
+fn radix_to_decimal(digits: &str, radix: u32) -> String {
+    if radix == 10 {
+        let digits = digits.trim_start_matches('0');
+        return if digits.is_empty() { "0".to_owned() } else { digits.to_owned() };
+    }
+    // Limbs of nine decimal digits, least significant first. Input digits are
+    // taken in chunks so that each pass over the limbs multiplies by as large a
+    // power of `radix` as fits in a u32.
+    const LIMB: u64 = 1_000_000_000;
+    let chunk = match radix {
+        2 => 31,
+        8 => 10,
+        16 => 7,
+        _ => 1,
+    };
+    let chars: Vec<u32> = digits.chars().map(|c| c.to_digit(radix).unwrap()).collect();
+    let mut out: Vec<u32> = vec![0];
+    for part in chars.chunks(chunk) {
+        let mut mul = 1u64;
+        let mut carry = 0u64;
+        for &d in part {
+            mul *= u64::from(radix);
+            carry = carry * u64::from(radix) + u64::from(d);
+        }
+        for limb in &mut out {
+            let v = u64::from(*limb) * mul + carry;
+            *limb = (v % LIMB) as u32;
+            carry = v / LIMB;
+        }
+        while carry > 0 {
+            out.push((carry % LIMB) as u32);
+            carry /= LIMB;
+        }
+    }
+    while out.len() > 1 && out[out.len() - 1] == 0 {
+        out.pop();
+    }
+    let mut decimal = out[out.len() - 1].to_string();
+    for limb in out.iter().rev().skip(1) {
+        decimal.push_str(&format!("{:09}", limb));
+    }
+    decimal
+}
+
+// Resolve a scalar tagged `!!bool`, `!!int`, `!!float` or `!!null` with the given
+// schema, producing `Yaml::BadValue` if it is not spelled as that type.
+fn resolve_typed(v: String, suffix: &str, schema: Schema) -> Yaml {
+    // An explicit tag asks for the type even if the schema never infers it.
+    let schema = if schema == Schema::Failsafe { Schema::Core } else { schema };
+    match (suffix, Yaml::from_str_with_schema(&v, schema)) {
+        ("bool", b @ Yaml::Boolean(_))
+            | ("int", b @ Yaml::Integer(_))
+            | ("int", b @ Yaml::BigInteger(_))
+            | ("float", b @ Yaml::Real(_))
+            | ("null", b @ Yaml::Null) => b,
+        ("float", Yaml::Integer(n)) => Yaml::Real(n.to_string()),
+        ("float", Yaml::BigInteger(n)) => Yaml::Real(n),
+        _ => Yaml::BadValue,
+    }
+}
+
+// Convert a collection tagged `!!set`, `!!omap` or `!!pairs` from its raw form.
+fn resolve_typed_collection(node: Yaml, suffix: &str, mark: Marker) -> Result<Yaml, ScanError> {
+    let invalid = |msg: &str| Err(ScanError::new(mark, &format!("invalid !!{}: {}", suffix, msg)));
+    match (suffix, node) {
+        ("set", Yaml::Hash(h)) => {
+            let mut set = Set::new();
+            for (k, v) in h {
+                if !v.is_null() {
+                    return invalid(&format!("the value of {} is not null", describe_key(&k)));
+                }
+                set.insert(k, ());
+            }
+            Ok(Yaml::Set(set))
+        },
+        ("omap", Yaml::Array(v)) | ("pairs", Yaml::Array(v)) => {
+            let mut pairs = Pairs::with_capacity(v.len());
+            for item in v {
+                let pair = match item {
+                    Yaml::Hash(h) => if h.len() == 1 { h.into_iter().next() } else { None },
+                    _ => None,
+                };
+                match pair {
+                    Some(pair) => pairs.push(pair),
+                    None => return invalid("expected a sequence of single-pair mappings"),
+                }
+            }
+            if suffix == "pairs" {
+                return Ok(Yaml::Pairs(pairs));
+            }
+            let mut omap = Hash::new();
+            for (k, v) in pairs {
+                if omap.contains_key(&k) {
+                    return invalid(&format!("duplicate key {}", describe_key(&k)));
+                }
+                omap.insert(k, v);
+            }
+            Ok(Yaml::OrderedMap(omap))
+        },
+        ("set", _) => invalid("expected a mapping"),
+        _ => invalid("expected a sequence"),
+    }
+}
+
+// A mapping key for use in error messages.
+fn describe_key(key: &Yaml) -> String {
+    match *key {
+        Yaml::String(ref k) => format!("{:?}", k),
+        ref k => format!("{:?}", k),
+    }
+}
+
+fn split_sign(v: &str) -> (&str, &str) {
+    if v.starts_with('-') || v.starts_with('+') {
+        v.split_at(1)
+    } else {
+        ("", v)
+    }
+}
+
+fn is_digits(v: &str, radix: u32) -> bool {
+    !v.is_empty() && v.chars().all(|c| c.is_digit(radix))
+}
+
+// Length of the leading run of decimal digits (and `_` if allowed) in `v`.
+fn digits_len(v: &str, underscores: bool) -> usize {
+    v.find(|c: char| !(c.is_digit(10) || (underscores && c == '_'))).unwrap_or_else(|| v.len())
+}
+
+// `[-+]?(0|[1-9][0-9]*)(\.[0-9]*)?([eE][-+]?[0-9]+)?` with a sign only if `json`
+// is false, and `-?` otherwise. Core floats may also start with the dot.
+fn is_float(v: &str, json: bool) -> bool {
+    let (sign, mut rest) = split_sign(v);
+    if json && sign == "+" {
+        return false;
+    }
+    let int_len = digits_len(rest, false);
+    if json && (int_len == 0 || (int_len > 1 && rest.starts_with('0'))) {
+        return false;
+    }
+    let mut digits = int_len;
+    rest = &rest[int_len..];
+    if rest.starts_with('.') {
+        let frac_len = digits_len(&rest[1..], false);
+        if json && frac_len == 0 {
+            return false;
+        }
+        digits += frac_len;
+        rest = &rest[1 + frac_len..];
+    }
+    if digits == 0 {
+        return false;
+    }
+    if rest.starts_with('e') || rest.starts_with('E') {
+        let (_, exp) = split_sign(&rest[1..]);
+        return is_digits(exp, 10);
+    }
+    rest.is_empty()
+}
+
+fn is_special_float(v: &str) -> bool {
+    match v {
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF"
+            | "-.inf" | "-.Inf" | "-.INF" | ".nan" | ".NaN" | ".NAN" => true,
+        _ => false,
+    }
+}
+
+// The value of a sexagesimal number `[0-9][0-9_]*(:[0-5]?[0-9])+`.
+fn parse_sexagesimal(v: &str) -> Option<i64> {
+    let mut parts = v.split(':');
+    let first = parts.next().unwrap().replace('_', "");
+    if !is_digits(&first, 10) {
+        return None;
+    }
+    let mut n = match first.parse::<i64>() {
+        Ok(n) => n,
+        Err(_) => return None,
+    };
+    let mut count = 0;
+    for part in parts {
+        if part.is_empty() || part.len() > 2 || !is_digits(part, 10) {
+            return None;
+        }
+        let digit = part.parse::<i64>().unwrap();
+        if digit >= 60 {
+            return None;
+        }
+        n = match n.checked_mul(60).and_then(|n| n.checked_add(digit)) {
+            Some(n) => n,
+            None => return None,
+        };
+        count += 1;
+    }
+    if count == 0 { None } else { Some(n) }
+}
+
+fn resolve_json(v: &str) -> Yaml {
+    match v {
+        "null" => Yaml::Null,
+        "true" => Yaml::Boolean(true),
+        "false" => Yaml::Boolean(false),
+        _ if is_float(v, true) => {
+            let (sign, digits) = split_sign(v);
+            if is_digits(digits, 10) {
+                integer(sign, digits, 10)
+            } else {
+                Yaml::Real(v.to_owned())
+            }
+        },
+        _ => Yaml::String(v.to_owned()),
+    }
+}
+
+fn resolve_core(v: &str) -> Yaml {
+    match v {
+        "" | "~" | "null" | "Null" | "NULL" => return Yaml::Null,
+        "true" | "True" | "TRUE" => return Yaml::Boolean(true),
+        "false" | "False" | "FALSE" => return Yaml::Boolean(false),
+        _ if is_special_float(v) => return Yaml::Real(v.to_owned()),
+        _ => {}
+    }
+    let radix = if v.starts_with("0o") { 8 } else if v.starts_with("0x") { 16 } else { 0 };
+    if radix != 0 {
+        if is_digits(&v[2..], radix) {
+            return integer("", &v[2..], radix);
+        }
+        return Yaml::String(v.to_owned());
+    }
+    let (sign, digits) = split_sign(v);
+    if is_digits(digits, 10) {
+        return integer(sign, digits, 10);
+    }
+    if is_float(v, false) {
+        return Yaml::Real(v.to_owned());
+    }
+    Yaml::String(v.to_owned())
+}
+
+fn resolve_yaml11_int(sign: &str, digits: &str) -> Option<Yaml> {
+    let (radix, body) = if digits.starts_with("0b") {
+        (2, &digits[2..])
+    } else if digits.starts_with("0x") {
+        (16, &digits[2..])
+    } else if digits.starts_with('0') && digits.len() > 1 {
+        (8, &digits[1..])
+    } else if digits.contains(':') {
+        if digits.starts_with('0') {
+            return None;
+        }
+        return parse_sexagesimal(digits).map(|n| Yaml::Integer(if sign == "-" { -n } else { n }));
+    } else {
+        (10, digits)
+    };
+    let body = body.replace('_', "");
+    if !is_digits(&body, radix) {
+        return None;
+    }
+    Some(integer(sign, &body, radix))
+}
+
+fn resolve_yaml11_float(sign: &str, digits: &str) -> Option<String> {
+    let dot = match digits.find('.') {
+        Some(dot) => dot,
+        None => return None,
+    };
+    let (int_part, frac_part) = digits.split_at(dot);
+    if int_part.contains(':') {
+        // sexagesimal, e.g. 190:20:30.15
+        if !frac_part[1..].chars().all(|c| c.is_digit(10) || c == '_') {
+            return None;
+        }
+        let frac = format!("0{}", frac_part.replace('_', ""));
+        return match (parse_sexagesimal(int_part), frac.parse::<f64>()) {
+            (Some(whole), Ok(frac)) => Some(format!("{}{}", sign, whole as f64 + frac)),
+            _ => None,
+        };
+    }
+    if !int_part.is_empty() && !int_part.starts_with(|c: char| c.is_digit(10)) {
+        return None;
+    }
+    let cleaned = format!("{}{}", sign, digits.replace('_', ""));
+    if is_float(&cleaned, false) && cleaned.contains(|c: char| c.is_digit(10)) {
+        Some(cleaned)
+    } else {
+        None
+    }
+}
+
+fn resolve_yaml11(v: &str) -> Yaml {
+    match v {
+        "" | "~" | "null" | "Null" | "NULL" => return Yaml::Null,
+        "yes" | "Yes" | "YES" | "true" | "True" | "TRUE" | "on" | "On" | "ON" => {
+            return Yaml::Boolean(true)
+        },
+        "no" | "No" | "NO" | "false" | "False" | "FALSE" | "off" | "Off" | "OFF" => {
+            return Yaml::Boolean(false)
+        },
+        _ if is_special_float(v) => return Yaml::Real(v.to_owned()),
+        _ => {}
+    }
+    let (sign, digits) = split_sign(v);
+    if !digits.starts_with(|c: char| c.is_digit(10) || c == '.') {
+        return Yaml::String(v.to_owned());
+    }
+    if let Some(n) = resolve_yaml11_int(sign, digits) {
+        return n;
+    }
+    if let Some(f) = resolve_yaml11_float(sign, digits) {
+        return Yaml::Real(f);
+    }
+    match Timestamp::parse(v) {
+        Some(t) => Yaml::Timestamp(t),
+        None => Yaml::String(v.to_owned()),
+    }
+}
+
+// Source positions of a node and its descendants, recorded alongside the `Yaml`
+// tree by `YamlLoader::load_marked`.
+#[derive(Clone, Debug)]
+struct Spans {
+    start: Marker,
+    end: Marker,
+    children: SpanChildren,
+}
+
+#[derive(Clone, Debug)]
+enum SpanChildren {
+    None,
+    Array(Vec<Spans>),
+    // keyed like the mapping itself, so that it stays in sync on duplicate keys
+    Hash(HashMap<Yaml, (Spans, Spans)>),
+}
+
+impl Spans {
+    fn new(start: Marker, end: Marker, children: SpanChildren) -> Spans {
+        Spans {
+            start: start,
+            end: end,
+            children: children,
+        }
+    }
+}
+
+/// A handler for a custom tag, see `YamlLoader::register_tag`.
+pub type TagHandler = Box<Fn(Yaml, Marker) -> Result<Yaml, ScanError>>;
+
+/// A source of values for `${VAR}` references, see `YamlLoader::variables`.
+pub type VariableSource = Box<Fn(&str) -> Option<String>>;
+
+/// How `YamlLoader` treats a key that occurs more than once in a mapping.
+#[derive(Clone, Copy, PartialEq, Debug, Eq)]
+pub enum DuplicateKeys {
+    /// The last value wins. This is the default.
+    Allow,
+    /// The last value wins, and the duplicate is recorded; see `YamlLoader::duplicates`.
+    Warn,
+    /// Loading fails.
+    Error,
+}
+
+/// A key found twice in the same mapping.
+#[derive(Clone, PartialEq, Debug)]
+pub struct DuplicateKey {
+    pub key: Yaml,
+    /// Position of the first occurrence.
+    pub first: Marker,
+    /// Position of the duplicate.
+    pub second: Marker,
+}
+
 pub struct YamlLoader {
     docs: Vec<Yaml>,
     // states
     // (current node, anchor_id) tuple
     doc_stack: Vec<(Yaml, usize)>,
     key_stack: Vec<Yaml>,
-    anchor_map: BTreeMap<usize, Yaml>,
+    // mappings pulled in through `<<` keys, one list per open mapping
+    merge_stack: Vec<Vec<(Yaml, Marker, Option<Spans>)>>,
+    // spans of the open nodes and pending keys, only kept by `load_marked`
+    span_stack: Vec<Spans>,
+    key_span_stack: Vec<Option<Spans>>,
+    anchor_spans: BTreeMap<usize, Spans>,
+    doc_spans: Vec<Spans>,
+    record_spans: bool,
+    // start mark, tag and expanded node count of each open collection
+    start_stack: Vec<Marker>,
+    tag_stack: Vec<Option<TokenType>>,
+    size_stack: Vec<usize>,
+    // positions of the keys seen in each open mapping, unless duplicates are allowed
+    seen_keys_stack: Vec<HashMap<Yaml, Marker>>,
+    duplicates: Vec<DuplicateKey>,
+    anchor_map: AnchorMap,
+    // expanded node count of each anchored node
+    anchor_sizes: BTreeMap<usize, usize>,
+    error: Option<ScanError>,
+    // budget usage: nodes as written, nodes after alias expansion, aliases
+    source_nodes: usize,
+    expanded_nodes: usize,
+    aliases: usize,
+
+    merge_keys: bool,
+    expand_aliases: bool,
+    duplicate_keys: DuplicateKeys,
+    preserve_tags: bool,
+    schema: Option<Schema>,
+    tag_handlers: HashMap<String, TagHandler>,
+    max_nodes: Option<usize>,
+    max_aliases: Option<usize>,
+    max_alias_ratio: Option<f64>,
+    max_depth: Option<usize>,
+    strict_json: bool,
+    variables: Option<VariableSource>,
 }
 
 impl MarkedEventReceiver for YamlLoader {
-    fn on_event(&mut self, ev: Event, _: Marker) {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        self.on_spanned_event(ev, mark, mark)
+    }
+
+    fn on_spanned_event(&mut self, ev: Event, mark: Marker, end: Marker) {
         // println!("EV {:?}", ev);
+        if self.error.is_some() {
+            return;
+        }
         match ev {
             Event::DocumentStart => {
                 // do nothing
@@ -89,73 +628,146 @@ impl MarkedEventReceiver for YamlLoader {
                     1 => self.docs.push(self.doc_stack.pop().unwrap().0),
                     _ => unreachable!()
                 }
+                if self.record_spans {
+                    let spans = self.span_stack.pop()
+                        .unwrap_or_else(|| Spans::new(mark, mark, SpanChildren::None));
+                    self.doc_spans.push(spans);
+                }
             },
-            Event::SequenceStart(aid) => {
+            Event::SequenceStart(aid, tag) => {
+                if !self.count_nodes(1, mark) {
+                    return;
+                }
+                self.size_stack.push(1);
+                self.start_stack.push(mark);
+                self.tag_stack.push(tag);
                 self.doc_stack.push((Yaml::Array(Vec::new()), aid));
+                if self.record_spans {
+                    self.span_stack.push(Spans::new(mark, mark, SpanChildren::Array(Vec::new())));
+                }
             },
             Event::SequenceEnd => {
-                let node = self.doc_stack.pop().unwrap();
-                self.insert_new_node(node);
+                let mut node = self.doc_stack.pop().unwrap();
+                let size = self.size_stack.pop().unwrap();
+                self.end_node_size(size, node.1);
+                let spans = self.end_spans(mark);
+                let start = self.start_stack.pop().unwrap();
+                let tag = self.tag_stack.pop().unwrap();
+                node.0 = match self.resolve_collection(node.0, tag, start) {
+                    Ok(n) => n,
+                    Err(e) => {
+                        self.error = Some(e);
+                        return;
+                    }
+                };
+                self.insert_new_node(node, start, false, spans);
             },
-            Event::MappingStart(aid) => {
+            Event::MappingStart(aid, tag) => {
+                if !self.count_nodes(1, mark) {
+                    return;
+                }
+                self.size_stack.push(1);
+                self.start_stack.push(mark);
+                self.tag_stack.push(tag);
                 self.doc_stack.push((Yaml::Hash(Hash::new()), aid));
                 self.key_stack.push(Yaml::BadValue);
+                self.merge_stack.push(Vec::new());
+                if self.duplicate_keys != DuplicateKeys::Allow {
+                    self.seen_keys_stack.push(HashMap::new());
+                }
+                if self.record_spans {
+                    self.span_stack.push(Spans::new(mark, mark, SpanChildren::Hash(HashMap::new())));
+                    self.key_span_stack.push(None);
+                }
             },
             Event::MappingEnd => {
                 self.key_stack.pop().unwrap();
-                let node = self.doc_stack.pop().unwrap();
-                self.insert_new_node(node);
+                let merges = self.merge_stack.pop().unwrap();
+                let mut node = self.doc_stack.pop().unwrap();
+                let mut spans = self.end_spans(mark);
+                if self.record_spans {
+                    self.key_span_stack.pop().unwrap();
+                }
+                if self.duplicate_keys != DuplicateKeys::Allow {
+                    self.seen_keys_stack.pop().unwrap();
+                }
+                if let Yaml::Hash(ref mut h) = node.0 {
+                    for (source, _, source_spans) in merges {
+                        merge_into(h, source);
+                        if let (Some(spans), Some(source_spans)) = (spans.as_mut(), source_spans) {
+                            if let SpanChildren::Hash(ref mut map) = spans.children {
+                                merge_spans_into(map, source_spans);
+                            }
+                        }
+                    }
+                }
+                let size = self.size_stack.pop().unwrap();
+                self.end_node_size(size, node.1);
+                let start = self.start_stack.pop().unwrap();
+                let tag = self.tag_stack.pop().unwrap();
+                node.0 = match self.resolve_collection(node.0, tag, start) {
+                    Ok(n) => n,
+                    Err(e) => {
+                        self.error = Some(e);
+                        return;
+                    }
+                };
+                self.insert_new_node(node, start, false, spans);
             },
             Event::Scalar(v, style, aid, tag) => {
-                let node = if style != TScalarStyle::Plain {
-                    Yaml::String(v)
-                } else if let Some(TokenType::Tag(ref handle, ref suffix)) = tag {
-                    // XXX tag:yaml.org,2002:
-                    if handle == "!!" {
-                        match suffix.as_ref() {
-                            "bool" => {
-                                // "true" or "false"
-                                match v.parse::<bool>() {
-                                    Err(_) => Yaml::BadValue,
-                                    Ok(v) => Yaml::Boolean(v)
-                                }
-                            },
-                            "int" => {
-                                match v.parse::<i64>() {
-                                    Err(_) => Yaml::BadValue,
-                                    Ok(v) => Yaml::Integer(v)
-                                }
-                            },
-                            "float" => {
-                                match parse_f64(&v) {
-                                    Some(_) => Yaml::Real(v),
-                                    None => Yaml::BadValue,
-                                }
-                            },
-                            "null" => {
-                                match v.as_ref() {
-                                    "~" | "null" => Yaml::Null,
-                                    _ => Yaml::BadValue,
-                                }
+                if !self.count_nodes(1, mark) {
+                    return;
+                }
+                self.end_node_size(1, aid);
+                let is_merge_key = self.merge_keys && style == TScalarStyle::Plain
+                    && tag.is_none() && v == "<<";
+                let v = match self.variables {
+                    Some(ref vars) if style == TScalarStyle::Plain || style == TScalarStyle::SingleQuoted
+                        || style == TScalarStyle::DoubleQuoted => {
+                        match interpolate(&v, vars, style, mark, end) {
+                            Ok(v) => v,
+                            Err(e) => {
+                                self.error = Some(e);
+                                return;
                             }
-                            _  => Yaml::String(v),
                         }
-                    } else {
-                        Yaml::String(v)
+                    },
+                    _ => v,
+                };
+                let node = match self.resolve_scalar(v, style, tag, mark) {
+                    Ok(node) => node,
+                    Err(e) => {
+                        self.error = Some(e);
+                        return;
                     }
-                } else {
-                    // Datatype is not specified, or unrecognized
-                    Yaml::from_str(&v)
                 };
 
-                self.insert_new_node((node, aid));
+                let spans = if self.record_spans {
+                    Some(Spans::new(mark, end, SpanChildren::None))
+                } else {
+                    None
+                };
+                self.insert_new_node((node, aid), mark, is_merge_key, spans);
             },
             Event::Alias(id) => {
+                let size = self.anchor_sizes.get(&id).cloned().unwrap_or(1);
+                if !self.count_alias(size, mark) {
+                    return;
+                }
+                self.end_node_size(size, 0);
                 let n = match self.anchor_map.get(&id) {
+                    Some(_) if !self.expand_aliases => Yaml::Alias(id),
                     Some(v) => v.clone(),
                     None => Yaml::BadValue,
                 };
-                self.insert_new_node((n, 0));
+                // an expanded alias keeps the positions of the anchored node's children
+                let spans = match self.anchor_spans.get(&id) {
+                    _ if !self.record_spans => None,
+                    Some(s) if self.expand_aliases =>
+                        Some(Spans::new(mark, mark, s.children.clone())),
+                    _ => Some(Spans::new(mark, mark, SpanChildren::None)),
+                };
+                self.insert_new_node((n, 0), mark, false, spans);
             }
             _ => { /* ignore */ }
         }
@@ -163,27 +775,632 @@ impl MarkedEventReceiver for YamlLoader {
     }
 }
 
+// Substitute the variable references in the scalar `v` of `style`, which spans
+// `start` to `end` in the source.
+fn interpolate(v: &str, vars: &VariableSource, style: TScalarStyle, start: Marker, end: Marker)
+    -> Result<String, ScanError> {
+    if !v.contains("${") {
+        return Ok(v.to_owned());
+    }
+    let len = v.chars().count();
+    let quotes = if style == TScalarStyle::Plain { 0 } else { 2 };
+    // the source position of the character at `offset`, when the scalar is written
+    // as its value on one line
+    let verbatim = start.line() == end.line() && end.index() - start.index() == len + quotes;
+    let at = |offset: usize| if verbatim {
+        let offset = offset + quotes / 2;
+        Marker::new(start.index() + offset, start.line(), start.col() + offset)
+    } else {
+        start
+    };
+
+    let chars: Vec<char> = v.chars().collect();
+    substitute(&chars, 0, vars, &at)
+}
+
+// How deeply variable references may be nested in the arguments of others.
+const MAX_VARIABLE_DEPTH: usize = 32;
+
+// Substitute the variable references in `chars`, which start at `offset` in the
+// scalar. `at` gives the position of an offset for errors.
+fn substitute(chars: &[char], offset: usize, vars: &VariableSource, at: &Fn(usize) -> Marker)
+    -> Result<String, ScanError> {
+    let mut out = String::with_capacity(chars.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '$' && chars.get(i + 1) == Some(&'$') && chars.get(i + 2) == Some(&'{') {
+            out.push_str("${");
+            i += 3;
+            continue;
+        }
+        if chars[i] != '$' || chars.get(i + 1) != Some(&'{') {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        let here = at(offset + i);
+        let name_end = i + 2 + chars[i + 2..].iter()
+            .take_while(|&&c| c == '_' || c.is_ascii_alphanumeric()).count();
+        let name: String = chars[i + 2..name_end].iter().collect();
+        let op = match (chars.get(name_end), chars.get(name_end + 1)) {
+            (Some(&'}'), _) => "",
+            (Some(&':'), Some(&'-')) => ":-",
+            (Some(&':'), Some(&'?')) => ":?",
+            (None, _) => return Err(ScanError::new(here, "unterminated variable reference")),
+            _ => {
+                let body: String = chars[i + 2..].iter().take_while(|&&c| c != '}').collect();
+                return Err(ScanError::new(here, &format!("invalid variable name {:?}", body)));
+            },
+        };
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(ScanError::new(here, &format!("invalid variable name {:?}", name)));
+        }
+        // the argument runs to the `}` matching this reference
+        let arg_start = name_end + op.len();
+        let mut close = arg_start;
+        let mut depth = 0;
+        loop {
+            match chars.get(close) {
+                None => return Err(ScanError::new(here, "unterminated variable reference")),
+                Some(&'}') if depth == 0 => break,
+                Some(&'}') => depth -= 1,
+                Some(&'$') if chars.get(close + 1) == Some(&'$') && chars.get(close + 2) == Some(&'{') => {
+                    close += 2;
+                },
+                Some(&'$') if chars.get(close + 1) == Some(&'{') => {
+                    depth += 1;
+                    if depth >= MAX_VARIABLE_DEPTH {
+                        return Err(ScanError::new(at(offset + close),
+                                                  "variable references are nested too deeply"));
+                    }
+                    close += 1;
+                },
+                _ => {},
+            }
+            close += 1;
+        }
+        // the argument is only substituted if it is used
+        let arg = &chars[arg_start..close];
+        match (vars(&name), op) {
+            (Some(ref value), _) if !value.is_empty() || op.is_empty() => out.push_str(value),
+            (_, ":-") => out.push_str(&try!(substitute(arg, offset + arg_start, vars, at))),
+            (_, ":?") if !arg.is_empty() => {
+                let message = try!(substitute(arg, offset + arg_start, vars, at));
+                return Err(ScanError::new(here, &message));
+            },
+            (Some(_), _) => return Err(ScanError::new(here, &format!("variable {} is empty", name))),
+            (None, _) => return Err(ScanError::new(here, &format!("variable {} is not set", name))),
+        }
+        i = close + 1;
+    }
+    Ok(out)
+}
+
+// Merge the entries of a `<<` source into `h`. Keys already present win, so
+// explicit keys override merged ones and earlier sources override later ones.
+fn merge_into(h: &mut Hash, source: Yaml) {
+    match source {
+        Yaml::Hash(src) => {
+            for (k, v) in src {
+                if !h.contains_key(&k) {
+                    h.insert(k, v);
+                }
+            }
+        },
+        Yaml::Array(srcs) => {
+            for src in srcs {
+                merge_into(h, src);
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+// Same as `merge_into`, for the spans of the merged entries.
+fn merge_spans_into(map: &mut HashMap<Yaml, (Spans, Spans)>, source: Spans) {
+    match source.children {
+        SpanChildren::Hash(src) => {
+            for (k, v) in src {
+                if !map.contains_key(&k) {
+                    map.insert(k, v);
+                }
+            }
+        },
+        SpanChildren::Array(srcs) => {
+            for src in srcs {
+                merge_spans_into(map, src);
+            }
+        },
+        SpanChildren::None => {},
+    }
+}
+
+// The full name of a tag as written, e.g. `!!str` or `!env`.
+fn tag_name(tag: &TokenType) -> String {
+    match *tag {
+        TokenType::Tag(ref handle, ref suffix) => format!("{}{}", handle, suffix),
+        _ => unreachable!(),
+    }
+}
+
+fn is_merge_source(node: &Yaml) -> bool {
+    match *node {
+        Yaml::Hash(_) => true,
+        Yaml::Array(ref v) => v.iter().all(|x| match *x {
+            Yaml::Hash(_) => true,
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
 impl YamlLoader {
-    fn insert_new_node(&mut self, node: (Yaml, usize)) {
+    pub fn new() -> YamlLoader {
+        YamlLoader {
+            docs: Vec::new(),
+            doc_stack: Vec::new(),
+            key_stack: Vec::new(),
+            merge_stack: Vec::new(),
+            span_stack: Vec::new(),
+            key_span_stack: Vec::new(),
+            anchor_spans: BTreeMap::new(),
+            doc_spans: Vec::new(),
+            record_spans: false,
+            start_stack: Vec::new(),
+            tag_stack: Vec::new(),
+            size_stack: Vec::new(),
+            seen_keys_stack: Vec::new(),
+            duplicates: Vec::new(),
+            anchor_map: BTreeMap::new(),
+            anchor_sizes: BTreeMap::new(),
+            error: None,
+            source_nodes: 0,
+            expanded_nodes: 0,
+            aliases: 0,
+
+            merge_keys: false,
+            expand_aliases: true,
+            duplicate_keys: DuplicateKeys::Allow,
+            preserve_tags: false,
+            schema: None,
+            tag_handlers: HashMap::new(),
+            max_nodes: None,
+            max_aliases: None,
+            max_alias_ratio: None,
+            max_depth: None,
+            strict_json: false,
+            variables: None,
+        }
+    }
+
+    /// Enable or disable [merge keys](http://yaml.org/type/merge.html).
+    ///
+    /// When enabled, a plain `<<` key whose value is a mapping, or a sequence of
+    /// mappings, is not stored; the entries of those mappings are merged into the
+    /// parent mapping instead. Keys written explicitly in the parent take precedence,
+    /// and earlier mappings of a sequence take precedence over later ones. Any other
+    /// value for `<<` makes loading fail.
+    pub fn merge_keys(&mut self, enable: bool) {
+        self.merge_keys = enable;
+    }
+
+    /// Enable or disable alias expansion (enabled by default).
+    ///
+    /// When disabled, each alias is loaded as a `Yaml::Alias` referring to the
+    /// anchored node instead of a deep copy of it, so a document costs memory in
+    /// proportion to its size rather than to its expanded size. The anchored nodes
+    /// of the last loaded stream are available from `anchors`; the aliases only
+    /// resolve through them (see `Yaml::Alias`).
+    pub fn expand_aliases(&mut self, expand: bool) {
+        self.expand_aliases = expand;
+    }
+
+    /// The anchored nodes of the stream loaded last.
+    pub fn anchors(&self) -> &AnchorMap {
+        &self.anchor_map
+    }
+
+    /// Choose how keys occurring more than once in a mapping are handled.
+    ///
+    /// With `DuplicateKeys::Error`, loading fails with an error at the second
+    /// occurrence whose message gives the position of the first one.
+    pub fn duplicate_keys(&mut self, policy: DuplicateKeys) {
+        self.duplicate_keys = policy;
+    }
+
+    /// Duplicate keys found in the stream loaded last with `DuplicateKeys::Warn`.
+    pub fn duplicates(&self) -> &[DuplicateKey] {
+        &self.duplicates
+    }
+
+    /// Resolve untagged plain scalars with `schema`, and accept its spellings for
+    /// scalars tagged `!!bool`, `!!int`, `!!float` or `!!null`. Without a schema the
+    /// loader uses `Yaml::from_str`.
+    pub fn schema(&mut self, schema: Schema) {
+        self.schema = Some(schema);
+    }
+
+    /// Enable or disable tag preservation.
+    ///
+    /// When enabled, a node whose tag is neither one of the standard tags the loader
+    /// resolves nor handled by `register_tag` is loaded as a `Yaml::Tagged`, which
+    /// `YamlEmitter` writes back with its tag. When disabled (the default), such tags
+    /// are dropped.
+    pub fn preserve_tags(&mut self, enable: bool) {
+        self.preserve_tags = enable;
+    }
+
+    /// Install a handler for nodes tagged `tag`, e.g. `"!env"`.
+    ///
+    /// The handler receives the node and the position of its first character, and
+    /// returns the node to load in its place, or an error that aborts loading. A
+    /// scalar is passed as a `Yaml::String` holding its raw text whatever its
+    /// style; a sequence or mapping is passed once all its children are loaded.
+    /// Handlers take precedence over the standard `!!` tags.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use yaml_rust::{Yaml, YamlLoader, ScanError};
+    ///
+    /// let mut loader = YamlLoader::new();
+    /// loader.register_tag("!upper", |node, mark| match node {
+    ///     Yaml::String(s) => Ok(Yaml::String(s.to_uppercase())),
+    ///     _ => Err(ScanError::new(mark, "!upper expects a scalar")),
+    /// });
+    /// let docs = loader.load("greeting: !upper hello").unwrap();
+    /// assert_eq!(docs[0]["greeting"].as_str().unwrap(), "HELLO");
+    /// assert!(loader.load("greeting: !upper [hello]").is_err());
+    /// ```
+    pub fn register_tag<F>(&mut self, tag: &str, handler: F)
+        where F: Fn(Yaml, Marker) -> Result<Yaml, ScanError> + 'static {
+        self.tag_handlers.insert(tag.to_owned(), Box::new(handler));
+    }
+
+    /// Limit the number of nodes a stream may contain once every alias is
+    /// expanded, counted across all documents. Unlimited by default.
+    ///
+    /// Together with `max_aliases` and `max_alias_ratio`, this protects against
+    /// "billion laughs" documents, whose few nested aliases expand to an enormous
+    /// tree. The limits are checked before an alias is expanded, and loading fails
+    /// with an error pointing at the first alias that exceeds them. They apply
+    /// whether or not aliases are expanded by the loader.
+    pub fn max_nodes(&mut self, limit: usize) {
+        self.max_nodes = Some(limit);
+    }
+
+    /// Limit the number of aliases a stream may contain. Unlimited by default.
+    pub fn max_aliases(&mut self, limit: usize) {
+        self.max_aliases = Some(limit);
+    }
+
+    /// Limit the ratio of expanded nodes to nodes written in the stream.
+    /// Unlimited by default.
+    pub fn max_alias_ratio(&mut self, ratio: f64) {
+        self.max_alias_ratio = Some(ratio);
+    }
+
+    /// Limit how deeply sequences and mappings may be nested. Unlimited by default.
+    /// See `Parser::max_depth`.
+    pub fn max_depth(&mut self, limit: usize) {
+        self.max_depth = Some(limit);
+    }
+
+    /// Accept only JSON text, failing on any YAML construct that JSON does not
+    /// have. See `Parser::strict_json`.
+    ///
+    /// ```
+    /// use yaml_rust::YamlLoader;
+    ///
+    /// let mut loader = YamlLoader::new();
+    /// loader.strict_json(true);
+    /// assert!(loader.load(r#"{"port": 8080, "hosts": ["a", "b"]}"#).is_ok());
+    /// let err = loader.load("{port: 8080}").unwrap_err();
+    /// assert_eq!(err.to_string(), "object keys must be double-quoted strings in JSON at line 1 column 2");
+    /// ```
+    pub fn strict_json(&mut self, strict: bool) {
+        self.strict_json = strict;
+    }
+
+    /// Substitute variable references in plain and quoted scalars, looking names up
+    /// with `source`. Block scalars are left as written.
+    ///
+    /// `${VAR}` is replaced by the value of `VAR`, and is an error if it is not
+    /// set. `${VAR:-default}` uses `default` if `VAR` is unset or empty, and
+    /// `${VAR:?message}` fails with `message` in that case. The default and the
+    /// message may hold references of their own, like `${VAR:-${OTHER}}`, which are
+    /// only substituted when used, and may be nested up to 32 deep. `$${` stands for
+    /// a literal `${`.
+    ///
+    /// Substitution happens before a plain scalar's type is resolved, so
+    /// `port: ${PORT}` loads as an integer, while a quoted scalar stays a string.
+    /// Errors point at the offending reference when the scalar is written on a
+    /// single line without escapes, and at the start of the scalar otherwise.
+    ///
+    /// References inside flow collections must be quoted, as `{` and `}` end plain
+    /// scalars there.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use yaml_rust::YamlLoader;
+    ///
+    /// let mut loader = YamlLoader::new();
+    /// loader.variables(|name| match name {
+    ///     "PORT" => Some("8080".to_owned()),
+    ///     _ => None,
+    /// });
+    /// let docs = loader.load("port: ${PORT}\nhost: \"${HOST:-localhost}\"").unwrap();
+    /// assert_eq!(docs[0]["port"].as_i64(), Some(8080));
+    /// assert_eq!(docs[0]["host"].as_str(), Some("localhost"));
+    ///
+    /// let err = loader.load("user: ${USER:?set USER to the service account}").unwrap_err();
+    /// assert_eq!(err.to_string(), "set USER to the service account at line 1 column 7");
+    /// ```
+    ///
+    /// To read the process environment, pass `|name| std::env::var(name).ok()`.
+    pub fn variables<F>(&mut self, source: F)
+        where F: Fn(&str) -> Option<String> + 'static {
+        self.variables = Some(Box::new(source));
+    }
+
+    // Account for `size` nodes about to be added to the tree.
+    fn count_nodes(&mut self, size: usize, mark: Marker) -> bool {
+        self.source_nodes += 1;
+        self.expanded_nodes = self.expanded_nodes.saturating_add(size);
+        match self.max_nodes {
+            Some(limit) if self.expanded_nodes > limit => {
+                self.error = Some(ScanError::new(mark,
+                    &format!("document exceeds the limit of {} nodes", limit)));
+                false
+            },
+            _ => true,
+        }
+    }
+
+    // Account for an alias expanding to `size` nodes.
+    fn count_alias(&mut self, size: usize, mark: Marker) -> bool {
+        self.aliases += 1;
+        if let Some(limit) = self.max_aliases {
+            if self.aliases > limit {
+                self.error = Some(ScanError::new(mark,
+                    &format!("document exceeds the limit of {} aliases", limit)));
+                return false;
+            }
+        }
+        if !self.count_nodes(size, mark) {
+            return false;
+        }
+        match self.max_alias_ratio {
+            Some(ratio) if self.expanded_nodes as f64 > ratio * self.source_nodes as f64 => {
+                self.error = Some(ScanError::new(mark,
+                    &format!("aliases expand the document more than {} times", ratio)));
+                false
+            },
+            _ => true,
+        }
+    }
+
+    // Add the size of a finished node to its parent, and remember it if the node
+    // is anchored.
+    fn end_node_size(&mut self, size: usize, anchor_id: usize) {
+        if anchor_id > 0 {
+            self.anchor_sizes.insert(anchor_id, size);
+        }
+        if let Some(parent) = self.size_stack.last_mut() {
+            *parent = parent.saturating_add(size);
+        }
+    }
+
+    // Convert a scalar to a node, according to its tag and style.
+    fn resolve_scalar(&self, v: String, style: TScalarStyle, tag: Option<TokenType>, mark: Marker)
+        -> Result<Yaml, ScanError> {
+        if let Some(handler) = tag.as_ref().and_then(|t| self.tag_handlers.get(&tag_name(t))) {
+            return handler(Yaml::String(v), mark);
+        }
+        if let Some(TokenType::Tag(ref handle, ref suffix)) = tag {
+            let standard = match (handle.as_ref(), suffix.as_ref()) {
+                ("!!", "bool") | ("!!", "int") | ("!!", "float") | ("!!", "null") | ("!!", "str")
+                    | ("!!", "timestamp") | ("!!", "binary") | ("", "!") => true,
+                _ => false,
+            };
+            if self.preserve_tags && !standard {
+                return Ok(Yaml::Tagged(Tag::new(handle, suffix), Box::new(Yaml::String(v))));
+            }
+        }
+        if let Some(TokenType::Tag(ref handle, ref suffix)) = tag {
+            if handle == "!!" && suffix == "binary" {
+                return match base64::decode(&v) {
+                    Ok(data) => Ok(Yaml::Binary(data)),
+                    Err(e) => Err(ScanError::new(mark, &format!("invalid base64 in !!binary scalar: {}", e))),
+                };
+            }
+        }
+        let node = if style != TScalarStyle::Plain {
+            Yaml::String(v)
+        } else if let Some(TokenType::Tag(ref handle, ref suffix)) = tag {
+            // XXX tag:yaml.org,2002:
+            if handle == "!!" {
+                match suffix.as_ref() {
+                    "bool" | "int" | "float" | "null" if self.schema.is_some() => {
+                        resolve_typed(v, suffix, self.schema.unwrap())
+                    },
+                    "bool" => {
+                        // "true" or "false"
+                        match v.parse::<bool>() {
+                            Err(_) => Yaml::BadValue,
+                            Ok(v) => Yaml::Boolean(v)
+                        }
+                    },
+                    "int" => {
+                        let (sign, digits) = split_sign(&v);
+                        if is_digits(digits, 10) {
+                            integer(sign, digits, 10)
+                        } else {
+                            Yaml::BadValue
+                        }
+                    },
+                    "float" => {
+                        match parse_f64(&v) {
+                            Some(_) => Yaml::Real(v),
+                            None => Yaml::BadValue,
+                        }
+                    },
+                    "null" => {
+                        match v.as_ref() {
+                            "~" | "null" => Yaml::Null,
+                            _ => Yaml::BadValue,
+                        }
+                    },
+                    "timestamp" => {
+                        match Timestamp::parse(&v) {
+                            Some(t) => Yaml::Timestamp(t),
+                            None => Yaml::BadValue,
+                        }
+                    },
+                    _  => Yaml::String(v),
+                }
+            } else {
+                Yaml::String(v)
+            }
+        } else {
+            // Datatype is not specified, or unrecognized
+            match self.schema {
+                Some(schema) => Yaml::from_str_with_schema(&v, schema),
+                None => Yaml::from_str(&v),
+            }
+        };
+        Ok(node)
+    }
+
+    // Convert a loaded sequence or mapping according to its tag.
+    fn resolve_collection(&self, node: Yaml, tag: Option<TokenType>, mark: Marker)
+        -> Result<Yaml, ScanError> {
+        if let Some(handler) = tag.as_ref().and_then(|t| self.tag_handlers.get(&tag_name(t))) {
+            return handler(node, mark);
+        }
+        match tag {
+            Some(TokenType::Tag(ref handle, ref suffix))
+                if handle == "!!" && (suffix == "set" || suffix == "omap" || suffix == "pairs") => {
+                resolve_typed_collection(node, suffix, mark)
+            },
+            Some(TokenType::Tag(ref handle, ref suffix)) if self.preserve_tags => {
+                match (handle.as_ref(), suffix.as_ref()) {
+                    ("!!", "seq") | ("!!", "map") | ("", "!") => Ok(node),
+                    _ => Ok(Yaml::Tagged(Tag::new(handle, suffix), Box::new(node))),
+                }
+            },
+            _ => Ok(node),
+        }
+    }
+
+    // Pop the spans of the collection ending at `mark`.
+    fn end_spans(&mut self, mark: Marker) -> Option<Spans> {
+        if !self.record_spans {
+            return None;
+        }
+        let mut spans = self.span_stack.pop().unwrap();
+        spans.end = mark;
+        Some(spans)
+    }
+
+    // Spans of a merge source, looking through aliases like the source itself.
+    fn merge_source_spans(&self, node: &Yaml, spans: Spans) -> Spans {
+        match *node {
+            Yaml::Alias(id) => self.anchor_spans.get(&id).cloned().unwrap_or(spans),
+            Yaml::Array(ref v) => match spans.children {
+                SpanChildren::Array(children) => Spans::new(spans.start, spans.end,
+                    SpanChildren::Array(v.iter().zip(children)
+                        .map(|(x, s)| self.merge_source_spans(x, s))
+                        .collect())),
+                children => Spans::new(spans.start, spans.end, children),
+            },
+            _ => spans,
+        }
+    }
+
+    fn insert_new_node(&mut self, node: (Yaml, usize), mark: Marker, is_merge_key: bool,
+                       spans: Option<Spans>) {
         // valid anchor id starts from 1
         if node.1 > 0 {
             self.anchor_map.insert(node.1, node.0.clone());
+            if let Some(ref s) = spans {
+                self.anchor_spans.insert(node.1, s.clone());
+            }
         }
         if self.doc_stack.is_empty() {
             self.doc_stack.push(node);
+            if let Some(s) = spans {
+                self.span_stack.push(s);
+            }
         } else {
             let parent = self.doc_stack.last_mut().unwrap();
             match *parent {
-                (Yaml::Array(ref mut v), _) => v.push(node.0),
+                (Yaml::Array(ref mut v), _) => {
+                    v.push(node.0);
+                    if let Some(s) = spans {
+                        if let SpanChildren::Array(ref mut children) =
+                                self.span_stack.last_mut().unwrap().children {
+                            children.push(s);
+                        }
+                    }
+                },
                 (Yaml::Hash(ref mut h), _) => {
                     let cur_key = self.key_stack.last_mut().unwrap();
                     // current node is a key
                     if cur_key.is_badvalue() {
-                        *cur_key = node.0;
+                        if let Some(seen) = self.seen_keys_stack.last_mut() {
+                            if !is_merge_key {
+                                if let Some(&first) = seen.get(&node.0) {
+                                    let dup = DuplicateKey {
+                                        key: node.0.clone(),
+                                        first: first,
+                                        second: mark,
+                                    };
+                                    if self.duplicate_keys == DuplicateKeys::Error {
+                                        self.error = Some(ScanError::new(mark, &format!(
+                                            "duplicate key {}, first defined at line {} column {}",
+                                            describe_key(&dup.key), first.line(), first.col() + 1)));
+                                    } else {
+                                        self.duplicates.push(dup);
+                                    }
+                                } else {
+                                    seen.insert(node.0.clone(), mark);
+                                }
+                            }
+                        }
+                        *cur_key = if is_merge_key { Yaml::Alias(MERGE_KEY) } else { node.0 };
+                        if spans.is_some() {
+                            *self.key_span_stack.last_mut().unwrap() = spans;
+                        }
+                    // current node is the value of a merge key
+                    } else if *cur_key == Yaml::Alias(MERGE_KEY) {
+                        *cur_key = Yaml::BadValue;
+                        // look through aliases, but keep nested ones shared
+                        let source = match *node.0.resolve(&self.anchor_map) {
+                            Yaml::Array(ref v) => Yaml::Array(v.iter()
+                                .map(|x| x.resolve(&self.anchor_map).clone())
+                                .collect()),
+                            ref v => v.clone(),
+                        };
+                        if is_merge_source(&source) {
+                            let spans = spans.map(|s| self.merge_source_spans(&node.0, s));
+                            self.merge_stack.last_mut().unwrap().push((source, mark, spans));
+                        } else {
+                            self.error = Some(ScanError::new(mark,
+                                "while merging, expected a mapping or a sequence of mappings"));
+                        }
                     // current node is a value
                     } else {
                         let mut newkey = Yaml::BadValue;
                         mem::swap(&mut newkey, cur_key);
+                        if let Some(s) = spans {
+                            let key_spans = self.key_span_stack.last_mut().unwrap().take().unwrap();
+                            if let SpanChildren::Hash(ref mut map) =
+                                    self.span_stack.last_mut().unwrap().children {
+                                map.insert(newkey.clone(), (key_spans, s));
+                            }
+                        }
                         h.insert(newkey, node.0);
                     }
                 },
@@ -192,16 +1409,51 @@ impl YamlLoader {
         }
     }
 
-    pub fn load_from_str(source: &str) -> Result<Vec<Yaml>, ScanError>{
-        let mut loader = YamlLoader {
-            docs: Vec::new(),
-            doc_stack: Vec::new(),
-            key_stack: Vec::new(),
-            anchor_map: BTreeMap::new(),
-        };
+    /// Load every document of `source` using the options set on this loader.
+    pub fn load(&mut self, source: &str) -> Result<Vec<Yaml>, ScanError> {
+        self.docs.clear();
+        self.doc_stack.clear();
+        self.key_stack.clear();
+        self.merge_stack.clear();
+        self.span_stack.clear();
+        self.key_span_stack.clear();
+        self.anchor_spans.clear();
+        self.doc_spans.clear();
+        self.start_stack.clear();
+        self.tag_stack.clear();
+        self.size_stack.clear();
+        self.seen_keys_stack.clear();
+        self.duplicates.clear();
+        self.anchor_map.clear();
+        self.anchor_sizes.clear();
+        self.error = None;
+        self.source_nodes = 0;
+        self.expanded_nodes = 0;
+        self.aliases = 0;
+
         let mut parser = Parser::new(source.chars());
-        try!(parser.load(&mut loader, true));
-        Ok(loader.docs)
+        if let Some(limit) = self.max_depth {
+            parser.max_depth(limit);
+        }
+        parser.strict_json(self.strict_json);
+        try!(parser.load(self, true));
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(mem::replace(&mut self.docs, Vec::new())),
+        }
+    }
+
+    /// Like `load`, but annotate every node with the position of its source text.
+    pub fn load_marked(&mut self, source: &str) -> Result<Vec<MarkedYaml>, ScanError> {
+        self.record_spans = true;
+        let docs = self.load(source);
+        self.record_spans = false;
+        let spans = mem::replace(&mut self.doc_spans, Vec::new());
+        Ok(try!(docs).into_iter().zip(spans).map(|(d, s)| MarkedYaml::new(d, s)).collect())
+    }
+
+    pub fn load_from_str(source: &str) -> Result<Vec<Yaml>, ScanError> {
+        YamlLoader::new().load(source)
     }
 }
 
@@ -227,6 +1479,29 @@ pub fn $name(&self) -> Option<$t> {
     );
 );
 
+macro_rules! define_checked_int (
+    ($name:ident, $t:ty) => (
+pub fn $name(&self) -> Result<$t, IntegerError> {
+    match *self {
+        Yaml::Integer(v) => v.to_string().parse::<$t>().map_err(|_| IntegerError::Overflow),
+        Yaml::BigInteger(ref v) => v.parse::<$t>().map_err(|_| IntegerError::Overflow),
+        _ => Err(IntegerError::NotAnInteger)
+    }
+}
+    );
+);
+
+macro_rules! define_as_mut (
+    ($name:ident, $t:ty, $yt:ident) => (
+pub fn $name(&mut self) -> Option<$t> {
+    match *self {
+        Yaml::$yt(ref mut v) => Some(v),
+        _ => None
+    }
+}
+    );
+);
+
 macro_rules! define_into (
     ($name:ident, $t:ty, $yt:ident) => (
 pub fn $name(self) -> Option<$t> {
@@ -241,17 +1516,115 @@ pub fn $name(self) -> Option<$t> {
 impl Yaml {
     define_as!(as_bool, bool, Boolean);
     define_as!(as_i64, i64, Integer);
+    define_as!(as_timestamp, Timestamp, Timestamp);
 
     define_as_ref!(as_str, &str, String);
+    define_as_ref!(as_binary, &[u8], Binary);
+    define_as_ref!(as_set, &Set, Set);
+    define_as_ref!(as_omap, &Hash, OrderedMap);
+    define_as_ref!(as_pairs, &Pairs, Pairs);
     define_as_ref!(as_hash, &Hash, Hash);
     define_as_ref!(as_vec, &Array, Array);
 
+    define_as_mut!(as_hash_mut, &mut Hash, Hash);
+    define_as_mut!(as_vec_mut, &mut Array, Array);
+
     define_into!(into_bool, bool, Boolean);
     define_into!(into_i64, i64, Integer);
+    define_into!(into_timestamp, Timestamp, Timestamp);
     define_into!(into_string, String, String);
+    define_into!(into_binary, Vec<u8>, Binary);
+    define_into!(into_set, Set, Set);
+    define_into!(into_omap, Hash, OrderedMap);
+    define_into!(into_pairs, Pairs, Pairs);
     define_into!(into_hash, Hash, Hash);
     define_into!(into_vec, Array, Array);
 
+    /// The value of `key` if this is a mapping containing it.
+    pub fn get(&self, key: &str) -> Option<&Yaml> {
+        self.as_hash().and_then(|h| h.get(&Yaml::String(key.to_owned())))
+    }
+
+    /// The value of `key`, mutably, if this is a mapping containing it.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Yaml> {
+        self.as_hash_mut().and_then(|h| h.get_mut(&Yaml::String(key.to_owned())))
+    }
+
+    // This node as a mapping, turning a null or bad value into an empty one first.
+    fn hash_or_insert(&mut self) -> &mut Hash {
+        if self.is_null() || self.is_badvalue() {
+            *self = Yaml::Hash(Hash::new());
+        }
+        match *self {
+            Yaml::Hash(ref mut h) => h,
+            ref v => panic!("expected a mapping, found {:?}", v),
+        }
+    }
+
+    /// The entry for `key` in this mapping, for in-place manipulation. A null node
+    /// is turned into an empty mapping first, so missing levels can be created with
+    /// `doc.entry("a").or_insert(Yaml::Null).entry("b")`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is neither a mapping nor null.
+    pub fn entry(&mut self, key: &str) -> Entry<'_, Yaml, Yaml> {
+        self.hash_or_insert().entry(Yaml::String(key.to_owned()))
+    }
+
+    /// Set `key` to `value` in this mapping, returning the previous value. An
+    /// existing key keeps its position; a new one is appended. A null node is
+    /// turned into an empty mapping first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is neither a mapping nor null.
+    pub fn insert(&mut self, key: &str, value: Yaml) -> Option<Yaml> {
+        match self.entry(key) {
+            Entry::Occupied(mut e) => Some(mem::replace(e.get_mut(), value)),
+            Entry::Vacant(e) => {
+                e.insert(value);
+                None
+            }
+        }
+    }
+
+    /// Remove `key` from this mapping, returning its value. Other nodes are left
+    /// unchanged.
+    pub fn remove(&mut self, key: &str) -> Option<Yaml> {
+        self.as_hash_mut().and_then(|h| h.remove(&Yaml::String(key.to_owned())))
+    }
+
+    /// Keep only the entries of this mapping for which `f(key, value)` returns
+    /// true, preserving their order. Other nodes are left unchanged; for sequences
+    /// use `as_vec_mut().retain(..)`.
+    pub fn retain<F: FnMut(&Yaml, &mut Yaml) -> bool>(&mut self, mut f: F) {
+        if let Yaml::Hash(ref mut h) = *self {
+            let removed: Vec<Yaml> = h.iter_mut()
+                .filter_map(|(k, v)| if f(k, v) { None } else { Some(k.clone()) })
+                .collect();
+            for k in removed {
+                h.remove(&k);
+            }
+        }
+    }
+
+    /// Append `value` to this sequence. A null node is turned into an empty
+    /// sequence first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is neither a sequence nor null.
+    pub fn push(&mut self, value: Yaml) {
+        if self.is_null() || self.is_badvalue() {
+            *self = Yaml::Array(Vec::new());
+        }
+        match *self {
+            Yaml::Array(ref mut v) => v.push(value),
+            ref v => panic!("expected a sequence, found {:?}", v),
+        }
+    }
+
     pub fn is_null(&self) -> bool {
         match *self {
             Yaml::Null => true,
@@ -273,6 +1646,22 @@ impl Yaml {
         }
     }
 
+    /// The tag of a `Yaml::Tagged` node.
+    pub fn tag(&self) -> Option<&Tag> {
+        match *self {
+            Yaml::Tagged(ref tag, _) => Some(tag),
+            _ => None
+        }
+    }
+
+    /// The node inside a `Yaml::Tagged`, or this node if it has no tag.
+    pub fn untagged(&self) -> &Yaml {
+        match *self {
+            Yaml::Tagged(_, ref v) => v,
+            _ => self
+        }
+    }
+
     pub fn as_f64(&self) -> Option<f64> {
         match *self {
             Yaml::Real(ref v) => parse_f64(v),
@@ -286,6 +1675,65 @@ impl Yaml {
             _ => None
         }
     }
+
+    define_checked_int!(checked_i64, i64);
+    define_checked_int!(checked_u64, u64);
+    define_checked_int!(checked_i128, i128);
+    define_checked_int!(checked_u128, u128);
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.checked_u64().ok()
+    }
+
+    pub fn as_i128(&self) -> Option<i128> {
+        self.checked_i128().ok()
+    }
+
+    pub fn as_u128(&self) -> Option<u128> {
+        self.checked_u128().ok()
+    }
+
+    #[cfg(feature = "num-bigint")]
+    pub fn as_big_int(&self) -> Option<BigInt> {
+        match *self {
+            Yaml::Integer(v) => Some(BigInt::from(v)),
+            Yaml::BigInteger(ref v) => v.parse().ok(),
+            _ => None
+        }
+    }
+
+    /// Follow a `Yaml::Alias` to the node it refers to. Other nodes, and aliases
+    /// missing from `anchors`, are returned unchanged.
+    pub fn resolve<'a>(&'a self, anchors: &'a AnchorMap) -> &'a Yaml {
+        let mut node = self;
+        while let Yaml::Alias(id) = *node {
+            match anchors.get(&id) {
+                Some(v) => node = v,
+                None => break,
+            }
+        }
+        node
+    }
+
+    /// Return a copy of this node with every `Yaml::Alias` replaced by a deep copy
+    /// of the node it refers to.
+    pub fn expand(&self, anchors: &AnchorMap) -> Yaml {
+        match *self.resolve(anchors) {
+            Yaml::Array(ref v) => Yaml::Array(v.iter().map(|x| x.expand(anchors)).collect()),
+            Yaml::Hash(ref h) => Yaml::Hash(h.iter()
+                .map(|(k, v)| (k.expand(anchors), v.expand(anchors)))
+                .collect()),
+            Yaml::Tagged(ref tag, ref v) => Yaml::Tagged(tag.clone(), Box::new(v.expand(anchors))),
+            Yaml::Set(ref s) => Yaml::Set(s.keys().map(|k| (k.expand(anchors), ())).collect()),
+            Yaml::OrderedMap(ref h) => Yaml::OrderedMap(h.iter()
+                .map(|(k, v)| (k.expand(anchors), v.expand(anchors)))
+                .collect()),
+            Yaml::Pairs(ref p) => Yaml::Pairs(p.iter()
+                .map(|&(ref k, ref v)| (k.expand(anchors), v.expand(anchors)))
+                .collect()),
+            ref node => node.clone(),
+        }
+    }
 }
 
 #[cfg_attr(feature = "cargo-clippy", allow(should_implement_trait))]
@@ -298,12 +1746,18 @@ impl Yaml {
             if n.is_ok() {
                 return Yaml::Integer(n.unwrap());
             }
+            if is_digits(&v[2..], 16) {
+                return integer("", &v[2..], 16);
+            }
         }
         if v.starts_with("0o") {
             let n = i64::from_str_radix(&v[2..], 8);
             if n.is_ok() {
                 return Yaml::Integer(n.unwrap());
             }
+            if is_digits(&v[2..], 8) {
+                return integer("", &v[2..], 8);
+            }
         }
         if v.starts_with('+') && v[1..].parse::<i64>().is_ok() {
             return Yaml::Integer(v[1..].parse::<i64>().unwrap());
@@ -313,9 +1767,36 @@ impl Yaml {
             "true" => Yaml::Boolean(true),
             "false" => Yaml::Boolean(false),
             _ if v.parse::<i64>().is_ok() => Yaml::Integer(v.parse::<i64>().unwrap()),
+            // too large for i64
+            _ if is_digits(split_sign(v).1, 10) => {
+                let (sign, digits) = split_sign(v);
+                integer(sign, digits, 10)
+            },
             // try parsing as f64
             _ if parse_f64(v).is_some() => Yaml::Real(v.to_owned()),
-            _ => Yaml::String(v.to_owned())
+            _ => match Timestamp::parse(v) {
+                Some(t) => Yaml::Timestamp(t),
+                None => Yaml::String(v.to_owned())
+            }
+        }
+    }
+
+    /// Resolve a plain scalar according to `schema`, falling back to `Yaml::String`.
+    ///
+    /// ```
+    /// use yaml_rust::Yaml;
+    /// use yaml_rust::yaml::Schema;
+    ///
+    /// assert_eq!(Yaml::from_str_with_schema("TRUE", Schema::Core), Yaml::Boolean(true));
+    /// assert_eq!(Yaml::from_str_with_schema("TRUE", Schema::Json), Yaml::String("TRUE".to_owned()));
+    /// assert_eq!(Yaml::from_str_with_schema("0777", Schema::Yaml11), Yaml::Integer(511));
+    /// ```
+    pub fn from_str_with_schema(v: &str, schema: Schema) -> Yaml {
+        match schema {
+            Schema::Failsafe => Yaml::String(v.to_owned()),
+            Schema::Json => resolve_json(v),
+            Schema::Core => resolve_core(v),
+            Schema::Yaml11 => resolve_yaml11(v),
         }
     }
 }
@@ -348,6 +1829,187 @@ impl Index<usize> for Yaml {
     }
 }
 
+impl<'a> IndexMut<&'a str> for Yaml {
+    /// The value of `idx` in this mapping, inserting `Yaml::Null` if it is missing.
+    /// A null node is turned into an empty mapping first, so `doc["a"]["b"] = v`
+    /// creates `a` as needed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is neither a mapping nor null.
+    fn index_mut(&mut self, idx: &'a str) -> &mut Yaml {
+        self.entry(idx).or_insert(Yaml::Null)
+    }
+}
+
+impl IndexMut<usize> for Yaml {
+    /// The element `idx` of this sequence, or the value of the integer key `idx` in
+    /// this mapping, inserting `Yaml::Null` if it is missing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out of bounds, or if this is neither a sequence nor a
+    /// mapping.
+    fn index_mut(&mut self, idx: usize) -> &mut Yaml {
+        match *self {
+            Yaml::Array(ref mut v) => &mut v[idx],
+            Yaml::Hash(ref mut h) => h.entry(Yaml::Integer(idx as i64)).or_insert(Yaml::Null),
+            ref v => panic!("expected a sequence or a mapping, found {:?}", v),
+        }
+    }
+}
+
+/// A `Yaml` node annotated with the position of its source text, as loaded by
+/// `YamlLoader::load_marked`.
+///
+/// The markers of a node reached through an expanded alias point at the alias,
+/// while those of its children point into the anchored node.
+///
+/// # Examples
+///
+/// ```
+/// use yaml_rust::YamlLoader;
+/// let docs = YamlLoader::new().load_marked("server:\n  port: 80a").unwrap();
+/// let port = &docs[0]["server"]["port"];
+/// assert_eq!(port.as_str().unwrap(), "80a");
+/// assert_eq!(port.start.line(), 2);
+/// assert_eq!(port.start.col(), 8);
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub struct MarkedYaml {
+    /// Position of the first character of the node.
+    pub start: Marker,
+    /// Position just past the node. For collections, the position of the token
+    /// that closes them.
+    pub end: Marker,
+    pub node: MarkedNode,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum MarkedNode {
+    /// Any node that is neither a sequence nor a mapping.
+    Scalar(Yaml),
+    Array(Vec<MarkedYaml>),
+    /// Marked keys and values, indexed by their plain key.
+    Hash(LinkedHashMap<Yaml, (MarkedYaml, MarkedYaml)>),
+}
+
+impl MarkedYaml {
+    fn new(yaml: Yaml, spans: Spans) -> MarkedYaml {
+        let (start, end) = (spans.start, spans.end);
+        let node = match (yaml, spans.children) {
+            (Yaml::Array(v), SpanChildren::Array(children)) =>
+                MarkedNode::Array(v.into_iter().zip(children)
+                    .map(|(x, s)| MarkedYaml::new(x, s))
+                    .collect()),
+            (Yaml::Hash(h), SpanChildren::Hash(mut children)) =>
+                MarkedNode::Hash(h.into_iter().map(|(k, v)| {
+                    let (ks, vs) = children.remove(&k).unwrap_or_else(|| {
+                        (Spans::new(start, end, SpanChildren::None),
+                         Spans::new(start, end, SpanChildren::None))
+                    });
+                    (k.clone(), (MarkedYaml::new(k, ks), MarkedYaml::new(v, vs)))
+                }).collect()),
+            (yaml, _) => MarkedNode::Scalar(yaml),
+        };
+        MarkedYaml {
+            start: start,
+            end: end,
+            node: node,
+        }
+    }
+
+    /// The scalar value of this node, or `Yaml::BadValue` for collections.
+    pub fn as_scalar(&self) -> &Yaml {
+        match self.node {
+            MarkedNode::Scalar(ref v) => v,
+            _ => &BAD_VALUE,
+        }
+    }
+
+    pub fn as_vec(&self) -> Option<&Vec<MarkedYaml>> {
+        match self.node {
+            MarkedNode::Array(ref v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_hash(&self) -> Option<&LinkedHashMap<Yaml, (MarkedYaml, MarkedYaml)>> {
+        match self.node {
+            MarkedNode::Hash(ref h) => Some(h),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        self.as_scalar().as_bool()
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_scalar().as_i64()
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        self.as_scalar().as_f64()
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_scalar().as_str()
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.as_scalar().is_null()
+    }
+
+    pub fn is_badvalue(&self) -> bool {
+        self.as_scalar().is_badvalue()
+    }
+
+    /// Strip the markers, returning the plain `Yaml` tree.
+    pub fn into_yaml(self) -> Yaml {
+        match self.node {
+            MarkedNode::Scalar(v) => v,
+            MarkedNode::Array(v) => Yaml::Array(v.into_iter().map(MarkedYaml::into_yaml).collect()),
+            MarkedNode::Hash(h) => Yaml::Hash(h.into_iter()
+                .map(|(_, (k, v))| (k.into_yaml(), v.into_yaml()))
+                .collect()),
+        }
+    }
+}
+
+static BAD_MARKED: MarkedYaml = MarkedYaml {
+    start: ORIGIN,
+    end: ORIGIN,
+    node: MarkedNode::Scalar(Yaml::BadValue),
+};
+
+impl<'a> Index<&'a str> for MarkedYaml {
+    type Output = MarkedYaml;
+
+    fn index(&self, idx: &'a str) -> &MarkedYaml {
+        let key = Yaml::String(idx.to_owned());
+        match self.as_hash() {
+            Some(h) => h.get(&key).map(|e| &e.1).unwrap_or(&BAD_MARKED),
+            None => &BAD_MARKED
+        }
+    }
+}
+
+impl Index<usize> for MarkedYaml {
+    type Output = MarkedYaml;
+
+    fn index(&self, idx: usize) -> &MarkedYaml {
+        if let Some(v) = self.as_vec() {
+            v.get(idx).unwrap_or(&BAD_MARKED)
+        } else if let Some(v) = self.as_hash() {
+            let key = Yaml::Integer(idx as i64);
+            v.get(&key).map(|e| &e.1).unwrap_or(&BAD_MARKED)
+        } else {
+            &BAD_MARKED
+        }
+    }
+}
+
 impl IntoIterator for Yaml {
     type Item = Yaml;
     type IntoIter = YamlIter;
@@ -638,4 +2300,630 @@ c: ~
         let first = out.into_iter().next().unwrap();
         assert_eq!(first[0]["important"].as_bool().unwrap(), true);
     }
+
+    #[test]
+    fn test_merge_keys() {
+        let s = "
+defaults: &defaults
+    adapter: postgres
+    host: localhost
+extra: &extra
+    host: db.example.com
+    pool: 5
+dev:
+    <<: *defaults
+    database: dev
+prod:
+    database: prod
+    <<: [*extra, *defaults]
+    host: db.prod
+";
+        let mut loader = YamlLoader::new();
+        loader.merge_keys(true);
+        let out = loader.load(&s).unwrap();
+        let doc = &out[0];
+        assert_eq!(doc["dev"]["adapter"].as_str().unwrap(), "postgres");
+        assert_eq!(doc["dev"]["host"].as_str().unwrap(), "localhost");
+        assert_eq!(doc["dev"]["database"].as_str().unwrap(), "dev");
+        assert!(doc["dev"]["<<"].is_badvalue());
+        assert_eq!(doc["prod"]["host"].as_str().unwrap(), "db.prod");
+        assert_eq!(doc["prod"]["pool"].as_i64().unwrap(), 5);
+        assert_eq!(doc["prod"]["adapter"].as_str().unwrap(), "postgres");
+
+        // merge keys are ordinary keys unless enabled
+        let out = YamlLoader::load_from_str(&s).unwrap();
+        assert!(!out[0]["dev"]["<<"].is_badvalue());
+    }
+
+    #[test]
+    fn test_merge_keys_bad_value() {
+        let s = "
+a: &a 1
+b:
+    <<: *a
+";
+        let mut loader = YamlLoader::new();
+        loader.merge_keys(true);
+        let err = loader.load(&s).unwrap_err();
+        assert_eq!(err.marker().line(), 4);
+        assert!(loader.load("b: {<<: [{x: 1}, 2]}").is_err());
+        assert!(loader.load("b: {'<<': 1}").is_ok());
+    }
+
+    #[test]
+    fn test_shared_aliases() {
+        let s = "
+a: &a [x, y]
+b: &b [*a, *a, *a]
+c: [*b, *b]
+d:
+    <<: {k: *a}
+";
+        let mut loader = YamlLoader::new();
+        loader.expand_aliases(false);
+        loader.merge_keys(true);
+        let out = loader.load(&s).unwrap();
+        let doc = &out[0];
+        let anchors = loader.anchors();
+        assert_eq!(doc["a"][1].as_str().unwrap(), "y");
+        assert_eq!(doc["c"][1], Yaml::Alias(2));
+        assert_eq!(doc["c"][1].resolve(anchors)[0], Yaml::Alias(1));
+        assert_eq!(doc["c"][1].resolve(anchors)[0].resolve(anchors)[0].as_str().unwrap(), "x");
+        assert_eq!(doc["d"]["k"], Yaml::Alias(1));
+
+        let expanded = doc.expand(anchors);
+        let mut loader = YamlLoader::new();
+        loader.merge_keys(true);
+        assert_eq!(expanded, loader.load(&s).unwrap()[0]);
+        assert_eq!(expanded["c"][1][2][0].as_str().unwrap(), "x");
+    }
+
+    #[test]
+    fn test_alias_budget() {
+        let s = "
+a: &a [lol, lol, lol, lol, lol, lol, lol, lol, lol]
+b: &b [*a, *a, *a, *a, *a, *a, *a, *a, *a]
+c: &c [*b, *b, *b, *b, *b, *b, *b, *b, *b]
+d: &d [*c, *c, *c, *c, *c, *c, *c, *c, *c]
+";
+        assert!(YamlLoader::load_from_str(&s).is_ok());
+
+        let mut loader = YamlLoader::new();
+        loader.max_nodes(1000);
+        let err = loader.load(&s).unwrap_err();
+        assert_eq!(err.marker().line(), 5);
+        assert!(loader.load("[a, b, c]").is_ok());
+
+        let mut loader = YamlLoader::new();
+        loader.max_aliases(9);
+        let err = loader.load(&s).unwrap_err();
+        assert_eq!(err.marker().line(), 4);
+        assert_eq!(err.marker().col(), 7);
+
+        let mut loader = YamlLoader::new();
+        loader.max_alias_ratio(10.0);
+        let err = loader.load(&s).unwrap_err();
+        assert_eq!(err.marker().line(), 4);
+        assert!(loader.load("a: &a [1, 2, 3]\nb: *a").is_ok());
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let mut s = String::new();
+        for _ in 0..10000 {
+            s.push('[');
+        }
+        let mut loader = YamlLoader::new();
+        loader.max_depth(100);
+        let err = loader.load(&s).unwrap_err();
+        assert_eq!(err.marker().col(), 100);
+        assert!(loader.load("[[[{a: [b]}]]]").is_ok());
+    }
+
+    #[test]
+    fn test_load_marked() {
+        let s = "
+base: &base
+    host: localhost
+    ports: [80, 'https']
+server:
+    <<: *base
+    name: |
+        web
+copy: *base
+";
+        let mut loader = YamlLoader::new();
+        loader.merge_keys(true);
+        let out = loader.load_marked(&s).unwrap();
+        let doc = &out[0];
+        assert_eq!(doc.start.line(), 2);
+
+        let ports = &doc["base"]["ports"];
+        assert_eq!((ports.start.line(), ports.start.col()), (4, 11));
+        assert_eq!((ports.end.line(), ports.end.col()), (4, 23));
+        assert_eq!((ports[1].start.col(), ports[1].end.col()), (16, 23));
+        assert_eq!(ports[0].as_i64().unwrap(), 80);
+
+        let name = &doc["server"]["name"];
+        assert_eq!(name.as_str().unwrap(), "web\n");
+        assert_eq!((name.start.line(), name.end.line(), name.end.col()), (8, 8, 11));
+        let host = &doc["server"]["host"];
+        assert_eq!((host.start.line(), host.start.col()), (3, 10));
+        let key = &doc["server"].as_hash().unwrap()[&Yaml::String("name".to_owned())].0;
+        assert_eq!((key.start.line(), key.start.col(), key.end.col()), (7, 4, 8));
+
+        assert_eq!(doc["copy"].start.line(), 9);
+        assert_eq!(doc["copy"]["host"].start.line(), 3);
+        assert!(doc["copy"]["nope"].is_badvalue());
+
+        let plain = loader.load(&s).unwrap();
+        assert_eq!(out.into_iter().next().unwrap().into_yaml(), plain[0]);
+    }
+
+    #[test]
+    fn test_duplicate_keys() {
+        let s = "
+server:
+    port: 80
+    host: a
+    port: 8080
+? [1, 2]
+: x
+? [1, 2]
+: y
+";
+        let out = YamlLoader::load_from_str(&s).unwrap();
+        assert_eq!(out[0]["server"]["port"].as_i64().unwrap(), 8080);
+
+        let mut loader = YamlLoader::new();
+        loader.duplicate_keys(DuplicateKeys::Warn);
+        let out = loader.load(&s).unwrap();
+        assert_eq!(out[0]["server"]["port"].as_i64().unwrap(), 8080);
+        let dups = loader.duplicates();
+        assert_eq!(dups.len(), 2);
+        assert_eq!(dups[0].key, Yaml::String("port".to_owned()));
+        assert_eq!((dups[0].first.line(), dups[0].second.line()), (3, 5));
+        assert_eq!((dups[1].first.line(), dups[1].second.line()), (6, 8));
+
+        loader.duplicate_keys(DuplicateKeys::Error);
+        let err = loader.load(&s).unwrap_err();
+        assert_eq!(err.marker().line(), 5);
+        assert_eq!(format!("{}", err),
+                   "duplicate key \"port\", first defined at line 3 column 5 at line 5 column 5");
+        assert!(loader.load("{a: 1, b: {a: 2}}").is_ok());
+    }
+
+    #[test]
+    fn test_tag_handlers() {
+        let s = "
+home: !env HOME
+quoted: !env 'HOME'
+timeout: !duration 5m
+ports: !sum [1, 2, 3]
+plain: !unknown x
+number: !!int 3
+";
+        let mut loader = YamlLoader::new();
+        loader.register_tag("!env", |node, mark| match node.as_str() {
+            Some("HOME") => Ok(Yaml::String("/home/yaml".to_owned())),
+            _ => Err(ScanError::new(mark, "unknown variable")),
+        });
+        loader.register_tag("!duration", |node, mark| {
+            let s = node.into_string().unwrap();
+            match s.trim_right_matches('m').parse::<i64>() {
+                Ok(v) if s.ends_with('m') => Ok(Yaml::Integer(v * 60)),
+                _ => Err(ScanError::new(mark, "bad duration")),
+            }
+        });
+        loader.register_tag("!sum", |node, mark| match node {
+            Yaml::Array(v) => Ok(Yaml::Integer(v.iter().filter_map(Yaml::as_i64).sum())),
+            _ => Err(ScanError::new(mark, "!sum expects a sequence")),
+        });
+        loader.register_tag("!!int", |node, _| Ok(Yaml::String(node.into_string().unwrap() + "!")));
+        let out = loader.load(&s).unwrap();
+        let doc = &out[0];
+        assert_eq!(doc["home"].as_str().unwrap(), "/home/yaml");
+        assert_eq!(doc["quoted"].as_str().unwrap(), "/home/yaml");
+        assert_eq!(doc["timeout"].as_i64().unwrap(), 300);
+        assert_eq!(doc["ports"].as_i64().unwrap(), 6);
+        assert_eq!(doc["plain"].as_str().unwrap(), "x");
+        assert_eq!(doc["number"].as_str().unwrap(), "3!");
+
+        let err = loader.load("a: 1\nb: !env PATH").unwrap_err();
+        assert_eq!((err.marker().line(), err.marker().col()), (2, 8));
+        let err = loader.load("a:\n  b: !sum {x: 1}").unwrap_err();
+        assert_eq!((err.marker().line(), err.marker().col()), (2, 10));
+    }
+
+    #[test]
+    fn test_variables() {
+        let s = "
+port: ${PORT}
+url: http://${HOST}:${PORT}/
+single: '${HOST}'
+double: \"${PORT}\"
+default: ${MISSING:-fallback}
+empty: ${EMPTY:-5}
+escaped: $${HOST} and $5
+block: |
+  ${HOST}
+${KEY}: key
+";
+        let mut loader = YamlLoader::new();
+        loader.variables(|name| match name {
+            "PORT" => Some("8080".to_owned()),
+            "HOST" => Some("example.com".to_owned()),
+            "KEY" => Some("name".to_owned()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        });
+        let out = loader.load(s).unwrap();
+        let doc = &out[0];
+        assert_eq!(doc["port"].as_i64(), Some(8080));
+        let nested = loader.load("
+a: ${NOPE:-${HOST}}
+b: ${NOPE:-${EMPTY:-${PORT}}}/x}
+c: ${PORT:-${NOPE}}
+d: ${NOPE:-$${HOST}}
+").unwrap();
+        assert_eq!(nested[0]["a"].as_str(), Some("example.com"));
+        assert_eq!(nested[0]["b"].as_str(), Some("8080/x}"));
+        assert_eq!(nested[0]["c"].as_i64(), Some(8080));
+        assert_eq!(nested[0]["d"].as_str(), Some("${HOST}"));
+        let deep = format!("{}x{}", "${NOPE:-".repeat(32), "}".repeat(32));
+        assert_eq!(loader.load(&deep).unwrap()[0].as_str(), Some("x"));
+        assert_eq!(doc["url"].as_str(), Some("http://example.com:8080/"));
+        assert_eq!(doc["single"].as_str(), Some("example.com"));
+        assert_eq!(doc["double"].as_str(), Some("8080"));
+        assert_eq!(doc["default"].as_str(), Some("fallback"));
+        assert_eq!(doc["empty"].as_i64(), Some(5));
+        assert_eq!(doc["escaped"].as_str(), Some("${HOST} and $5"));
+        assert_eq!(doc["block"].as_str(), Some("${HOST}\n"));
+        assert_eq!(doc["name"].as_str(), Some("key"));
+
+        for &(s, err) in &[
+            ("a: 1\nb: x${NOPE}", "variable NOPE is not set at line 2 column 5"),
+            ("b: \"${EMPTY:?}\"", "variable EMPTY is empty at line 1 column 5"),
+            ("b: ${NOPE:?NOPE is required}", "NOPE is required at line 1 column 4"),
+            ("b: ${PORT", "unterminated variable reference at line 1 column 4"),
+            ("b: ${1X}", "invalid variable name \"1X\" at line 1 column 4"),
+            ("b: \"\\t${NOPE}\"", "variable NOPE is not set at line 1 column 4"),
+            ("b: ${NOPE}\n  more", "variable NOPE is not set at line 1 column 4"),
+            ("b: ${NOPE:?use x:-y}", "use x:-y at line 1 column 4"),
+            ("b: ${NOPE:?set ${HOST}}", "set example.com at line 1 column 4"),
+            ("b: ${NOPE:-${MISSING}}", "variable MISSING is not set at line 1 column 12"),
+            ("b: ${NOPE:-${PORT}", "unterminated variable reference at line 1 column 4"),
+            ("b: ${A:x}", "invalid variable name \"A:x\" at line 1 column 4"),
+            (&format!("b: {}x{}", "${NOPE:-".repeat(100_000), "}".repeat(100_000)),
+             "variable references are nested too deeply at line 1 column 260"),
+        ] {
+            assert_eq!(loader.load(s).unwrap_err().to_string(), err, "{:?}", s);
+        }
+        assert_eq!(YamlLoader::load_from_str("a: ${NOPE}").unwrap()[0]["a"].as_str(), Some("${NOPE}"));
+    }
+
+    #[test]
+    fn test_preserve_tags() {
+        let s = "
+bucket: !Ref MyBucket
+arn: !GetAtt [MyBucket, Arn]
+props: !Custom {a: 1}
+str: !!str 1
+plain: ! 2
+verbatim: !<tag:example.com,2000:x> y
+";
+        let out = YamlLoader::load_from_str(&s).unwrap();
+        assert_eq!(out[0]["bucket"].as_str().unwrap(), "MyBucket");
+
+        let mut loader = YamlLoader::new();
+        loader.preserve_tags(true);
+        let out = loader.load(&s).unwrap();
+        let doc = &out[0];
+        assert_eq!(doc["bucket"], Yaml::Tagged(Tag::new("!", "Ref"),
+                                               Box::new(Yaml::String("MyBucket".to_owned()))));
+        assert_eq!(doc["arn"].tag().unwrap().to_string(), "!GetAtt");
+        assert_eq!(doc["arn"].untagged()[1].as_str().unwrap(), "Arn");
+        assert_eq!(doc["props"].untagged()["a"].as_i64().unwrap(), 1);
+        assert_eq!(doc["str"].as_str().unwrap(), "1");
+        assert_eq!(doc["plain"].as_str().unwrap(), "2");
+        assert_eq!(doc["verbatim"].tag().unwrap().to_string(), "!<tag:example.com,2000:x>");
+    }
+
+    #[test]
+    fn test_schemas() {
+        fn resolve(v: &str, schema: Schema) -> Yaml {
+            Yaml::from_str_with_schema(v, schema)
+        }
+        fn real(v: &str) -> Yaml {
+            Yaml::Real(v.to_owned())
+        }
+        fn string(v: &str) -> Yaml {
+            Yaml::String(v.to_owned())
+        }
+
+        assert_eq!(resolve("true", Schema::Failsafe), string("true"));
+        assert_eq!(resolve("123", Schema::Failsafe), string("123"));
+
+        assert_eq!(resolve("null", Schema::Json), Yaml::Null);
+        assert_eq!(resolve("~", Schema::Json), string("~"));
+        assert_eq!(resolve("True", Schema::Json), string("True"));
+        assert_eq!(resolve("-12", Schema::Json), Yaml::Integer(-12));
+        assert_eq!(resolve("+12", Schema::Json), string("+12"));
+        assert_eq!(resolve("012", Schema::Json), string("012"));
+        assert_eq!(resolve("1.5e3", Schema::Json), real("1.5e3"));
+        assert_eq!(resolve("1.", Schema::Json), string("1."));
+        assert_eq!(resolve(".inf", Schema::Json), string(".inf"));
+
+        assert_eq!(resolve("NULL", Schema::Core), Yaml::Null);
+        assert_eq!(resolve("~", Schema::Core), Yaml::Null);
+        assert_eq!(resolve("True", Schema::Core), Yaml::Boolean(true));
+        assert_eq!(resolve("FALSE", Schema::Core), Yaml::Boolean(false));
+        assert_eq!(resolve("yes", Schema::Core), string("yes"));
+        assert_eq!(resolve("+12", Schema::Core), Yaml::Integer(12));
+        assert_eq!(resolve("012", Schema::Core), Yaml::Integer(12));
+        assert_eq!(resolve("0o17", Schema::Core), Yaml::Integer(15));
+        assert_eq!(resolve("0xff", Schema::Core), Yaml::Integer(255));
+        assert_eq!(resolve("0b11", Schema::Core), string("0b11"));
+        assert_eq!(resolve("1_000", Schema::Core), string("1_000"));
+        assert_eq!(resolve(".5", Schema::Core), real(".5"));
+        assert_eq!(resolve("-.Inf", Schema::Core).as_f64(), Some(f64::NEG_INFINITY));
+        assert!(resolve(".NaN", Schema::Core).as_f64().unwrap().is_nan());
+
+        assert_eq!(resolve("yes", Schema::Yaml11), Yaml::Boolean(true));
+        assert_eq!(resolve("Off", Schema::Yaml11), Yaml::Boolean(false));
+        assert_eq!(resolve("y", Schema::Yaml11), string("y"));
+        assert_eq!(resolve("0777", Schema::Yaml11), Yaml::Integer(511));
+        assert_eq!(resolve("-0b1010_0111", Schema::Yaml11), Yaml::Integer(-167));
+        assert_eq!(resolve("0x_0A_74_AE", Schema::Yaml11), Yaml::Integer(685230));
+        assert_eq!(resolve("+685_230", Schema::Yaml11), Yaml::Integer(685230));
+        assert_eq!(resolve("190:20:30", Schema::Yaml11), Yaml::Integer(685230));
+        assert_eq!(resolve("190:20:30.15", Schema::Yaml11).as_f64(), Some(685230.15));
+        assert_eq!(resolve("6.8523015e+5", Schema::Yaml11).as_f64(), Some(685230.15));
+        assert_eq!(resolve("685_230.15", Schema::Yaml11), real("685230.15"));
+        assert_eq!(resolve("0089", Schema::Yaml11), string("0089"));
+        assert_eq!(resolve("1:60", Schema::Yaml11), string("1:60"));
+
+        let s = "
+a: yes
+b: 0777
+c: !!bool off
+d: !!int 1_000
+e: !!float 1
+f: !!bool maybe
+g: 'yes'
+";
+        let mut loader = YamlLoader::new();
+        loader.schema(Schema::Yaml11);
+        let out = loader.load(&s).unwrap();
+        let doc = &out[0];
+        assert_eq!(doc["a"].as_bool(), Some(true));
+        assert_eq!(doc["b"].as_i64(), Some(511));
+        assert_eq!(doc["c"].as_bool(), Some(false));
+        assert_eq!(doc["d"].as_i64(), Some(1000));
+        assert_eq!(doc["e"].as_f64(), Some(1.0));
+        assert!(doc["f"].is_badvalue());
+        assert_eq!(doc["g"].as_str(), Some("yes"));
+
+        loader.schema(Schema::Failsafe);
+        let out = loader.load(&s).unwrap();
+        assert_eq!(out[0]["a"].as_str(), Some("yes"));
+        assert_eq!(out[0]["b"].as_str(), Some("0777"));
+    }
+
+    #[test]
+    fn test_big_integers() {
+        let s = "
+u64_max: 18446744073709551615
+i64_min: -9223372036854775808
+id: 123456789012345678901234567890
+neg: -000170141183460469231731687303715884105728
+hex: 0xffffffffffffffff
+oct: 0o1000000000000000000000
+tagged: !!int 18446744073709551616
+float: 1e400
+";
+        let out = YamlLoader::load_from_str(&s).unwrap();
+        let doc = &out[0];
+
+        assert_eq!(doc["u64_max"], Yaml::BigInteger("18446744073709551615".to_owned()));
+        assert_eq!(doc["u64_max"].as_i64(), None);
+        assert_eq!(doc["u64_max"].checked_i64(), Err(IntegerError::Overflow));
+        assert_eq!(doc["u64_max"].as_u64(), Some(u64::max_value()));
+        assert_eq!(doc["u64_max"].as_i128(), Some(u64::max_value() as i128));
+
+        assert_eq!(doc["i64_min"], Yaml::Integer(i64::min_value()));
+        assert_eq!(doc["i64_min"].checked_u64(), Err(IntegerError::Overflow));
+        assert_eq!(doc["i64_min"].as_i128(), Some(i64::min_value() as i128));
+
+        assert_eq!(doc["id"].as_str(), None);
+        assert_eq!(doc["id"].checked_u128(), Ok(123456789012345678901234567890));
+        assert_eq!(doc["neg"], Yaml::BigInteger("-170141183460469231731687303715884105728".to_owned()));
+        assert_eq!(doc["neg"].as_i128(), Some(i128::min_value()));
+        assert_eq!(doc["neg"].checked_u128(), Err(IntegerError::Overflow));
+
+        assert_eq!(doc["hex"].as_u64(), Some(u64::max_value()));
+        assert_eq!(doc["oct"].as_u64(), Some(1 << 63));
+        assert_eq!(doc["tagged"].as_u128(), Some(1 << 64));
+        assert_eq!(doc["float"].checked_i64(), Err(IntegerError::NotAnInteger));
+
+        assert_eq!(Yaml::from_str_with_schema("-18446744073709551616", Schema::Json).as_i128(),
+                   Some(-(1 << 64)));
+        assert_eq!(Yaml::from_str_with_schema("0x1_0000_0000_0000_0000", Schema::Yaml11).as_u128(),
+                   Some(1 << 64));
+    }
+
+    #[test]
+    fn test_long_integers() {
+        let started = ::std::time::Instant::now();
+        let digits = "7".repeat(100_000);
+        let out = YamlLoader::load_from_str(&format!("- -00{}\n- 0x{}", digits, "f".repeat(100_000))).unwrap();
+        assert_eq!(out[0][0], Yaml::BigInteger(format!("-{}", digits)));
+        match out[0][1] {
+            Yaml::BigInteger(ref n) => assert_eq!(n.len(), 120_412),
+            ref other => panic!("unexpected {:?}", other),
+        }
+        assert!(started.elapsed().as_secs() < 5);
+
+        assert_eq!(Yaml::from_str(&format!("0x1{}", "0".repeat(32))),
+                   Yaml::BigInteger("340282366920938463463374607431768211456".to_owned()));
+        assert_eq!(Yaml::from_str_with_schema(&format!("0b1{}", "0".repeat(64)), Schema::Yaml11),
+                   Yaml::BigInteger("18446744073709551616".to_owned()));
+        assert_eq!(Yaml::from_str("0o7777777777777777777777"),
+                   Yaml::BigInteger("73786976294838206463".to_owned()));
+    }
+
+    #[cfg(feature = "num-bigint")]
+    #[test]
+    fn test_big_int() {
+        let out = YamlLoader::load_from_str("[1, 123456789012345678901234567890123456789012]").unwrap();
+        assert_eq!(out[0][0].as_big_int(), Some(BigInt::from(1)));
+        assert_eq!(out[0][1].as_big_int().unwrap().to_string(),
+                   "123456789012345678901234567890123456789012");
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let s = "
+canonical: 2001-12-15T02:59:43.1Z
+spaced: 2001-12-14 21:59:43.10 -5
+date: 2002-12-14
+tagged: !!timestamp 2001-12-14t21:59:43.10-05:00
+bad: !!timestamp 2002-02-30
+quoted: '2002-12-14'
+not_a_date: 2002-12-14x
+";
+        let out = YamlLoader::load_from_str(&s).unwrap();
+        let doc = &out[0];
+        let t = doc["canonical"].as_timestamp().unwrap();
+        assert_eq!((t.year, t.month, t.day, t.hour, t.nanosecond), (2001, 12, 15, 2, 100_000_000));
+        assert_eq!(doc["spaced"].as_timestamp().unwrap().to_utc(), t);
+        assert_eq!(doc["tagged"].as_timestamp().unwrap().to_string(), "2001-12-15T02:59:43.1Z");
+        assert!(doc["date"].as_timestamp().unwrap().is_date());
+        assert!(doc["bad"].is_badvalue());
+        assert_eq!(doc["quoted"].as_str(), Some("2002-12-14"));
+        assert_eq!(doc["not_a_date"].as_str(), Some("2002-12-14x"));
+
+        assert!(Yaml::from_str_with_schema("2002-12-14", Schema::Yaml11).as_timestamp().is_some());
+        assert_eq!(Yaml::from_str_with_schema("2002-12-14", Schema::Core).as_str(), Some("2002-12-14"));
+    }
+
+    #[test]
+    fn test_binary() {
+        let s = "
+cert: !!binary |
+  R0lGODlhDAAMAIQAAP//9/X17unp5WZmZgAAAOfn515eXvPz7Y6OjuDg4J+fn5
+  OTk6enp56enmlpaWNjY6Ojo4SEhP/++f/++f/++f/++f/++f/++f/++f/++f/+
+inline: !!binary \"aGVsbG8=\"
+";
+        let out = YamlLoader::load_from_str(&s).unwrap();
+        let doc = &out[0];
+        let cert = doc["cert"].as_binary().unwrap();
+        assert_eq!(cert.len(), 93);
+        assert_eq!(&cert[..6], b"GIF89a");
+        assert_eq!(doc["inline"].as_binary(), Some(&b"hello"[..]));
+
+        let s = "
+a: 1
+b: !!binary |
+  aGVsbG8*
+";
+        let err = YamlLoader::load_from_str(&s).unwrap_err();
+        assert_eq!(format!("{}", err),
+                   "invalid base64 in !!binary scalar: invalid character '*' at line 4 column 3");
+    }
+
+    #[test]
+    fn test_typed_collections() {
+        let s = "
+set: !!set
+  ? Mark McGwire
+  ? Sammy Sosa
+  ? Ken Griff
+omap: !!omap
+  - Mark McGwire: 65
+  - Sammy Sosa: 63
+  - Ken Griffy: 58
+pairs: !!pairs
+  - meeting: with team.
+  - meeting: with boss.
+  - break: lunch.
+";
+        let out = YamlLoader::load_from_str(&s).unwrap();
+        let doc = &out[0];
+        let set = doc["set"].as_set().unwrap();
+        assert_eq!(set.len(), 3);
+        assert!(set.contains_key(&Yaml::String("Sammy Sosa".to_owned())));
+        let omap = doc["omap"].as_omap().unwrap();
+        assert_eq!(omap.keys().map(|k| k.as_str().unwrap()).collect::<Vec<_>>(),
+                   vec!["Mark McGwire", "Sammy Sosa", "Ken Griffy"]);
+        assert_eq!(omap[&Yaml::String("Ken Griffy".to_owned())].as_i64(), Some(58));
+        let pairs = doc["pairs"].as_pairs().unwrap();
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(pairs[1], (Yaml::String("meeting".to_owned()), Yaml::String("with boss.".to_owned())));
+
+        let mut loader = YamlLoader::new();
+        loader.preserve_tags(true);
+        assert!(loader.load(&s).unwrap()[0]["set"].as_set().is_some());
+
+        for &(s, msg) in &[
+            ("x: !!set {a: 1}", "invalid !!set: the value of \"a\" is not null at line 1 column 10"),
+            ("x: !!set [a]", "invalid !!set: expected a mapping at line 1 column 10"),
+            ("x: !!omap [{a: 1}, {a: 2}]", "invalid !!omap: duplicate key \"a\" at line 1 column 11"),
+            ("x: !!pairs [{a: 1, b: 2}]",
+             "invalid !!pairs: expected a sequence of single-pair mappings at line 1 column 12"),
+            ("x: !!omap {a: 1}", "invalid !!omap: expected a sequence at line 1 column 11"),
+        ] {
+            let err = YamlLoader::load_from_str(s).unwrap_err();
+            assert_eq!(format!("{}", err), msg);
+        }
+    }
+
+    #[test]
+    fn test_mutation() {
+        let mut doc = YamlLoader::load_from_str("
+a: 1
+b: [x]
+c: {d: 2}
+").unwrap().remove(0);
+        doc["a"] = Yaml::Integer(10);
+        doc["new"]["nested"] = Yaml::from_str("deep");
+        doc["b"][0] = Yaml::from_str("y");
+        doc["b"].push(Yaml::from_str("z"));
+        *doc.get_mut("c").unwrap().get_mut("d").unwrap() = Yaml::Integer(3);
+        doc.entry("list").or_insert(Yaml::Null).push(Yaml::Integer(1));
+        *doc.entry("count").or_insert(Yaml::Integer(0)) = Yaml::Integer(5);
+        assert_eq!(doc.insert("a", Yaml::Integer(11)), Some(Yaml::Integer(10)));
+        doc.as_hash_mut().unwrap().insert(Yaml::Integer(1), Yaml::Null);
+        doc[1] = Yaml::from_str("one");
+
+        assert_eq!(doc, YamlLoader::load_from_str("
+a: 11
+b: [y, z]
+c: {d: 3}
+new: {nested: deep}
+list: [1]
+count: 5
+1: one
+").unwrap()[0]);
+
+        assert_eq!(doc.remove("new").unwrap()["nested"].as_str(), Some("deep"));
+        assert_eq!(doc.remove("new"), None);
+        doc.retain(|k, v| {
+            if let Some(n) = v.as_i64() {
+                *v = Yaml::Integer(n + 1);
+            }
+            k.as_str().map_or(true, |k| k != "list")
+        });
+        assert_eq!(doc.get("a"), Some(&Yaml::Integer(12)));
+        assert_eq!(doc.get("list"), None);
+        assert_eq!(doc["b"].as_vec_mut().unwrap().pop(), Some(Yaml::from_str("z")));
+        assert_eq!(doc.as_hash().unwrap().len(), 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a mapping")]
+    fn test_index_mut_scalar() {
+        let mut doc = Yaml::Integer(1);
+        doc["a"] = Yaml::Null;
+    }
 }